@@ -0,0 +1,2630 @@
+use crate::cli::{Args, OutputFormat, PaneLayout, PathStyle, TmuxPane};
+use crate::clipboard::copy_to_clipboard;
+use crate::config::Config;
+use crate::events::{Events, Waker};
+use crate::fuzzy::fuzzy_match;
+use crate::keys::{Action, KeyBindings};
+use crate::open_tty;
+use crate::preview::{
+    cached_preview, find_in_preview, get_file_preview, mark_context_lines, new_preview_cache,
+    Prefetcher, Preview, PreviewCache, PreviewOptions, BAT_MISSING, BYTES_PER_MB,
+};
+use crate::rg_matches::{
+    get_rg_matches, spawn_rg_command, spawn_rg_search, GroupedMatches, MatchData, MatchSource,
+    ParseOptions, Parsed, RgMatch, Stats,
+};
+use crate::state::{load_history, load_position, position_key, save_history, save_position};
+use crate::theme::Theme;
+use crate::tree::{flat_rows, tree_rows, ListRow};
+
+use anyhow::{Context, Result};
+use atty::Stream;
+use crossterm::{
+    event::{
+        DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+        MouseButton, MouseEvent, MouseEventKind,
+    },
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Alignment, Constraint, Direction, Layout, Position, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal, TerminalOptions, Viewport,
+};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Rows the inline UI takes up with `--no-alt-screen`
+const INLINE_HEIGHT: u16 = 20;
+
+// Struct to ensure the terminal is restored on exit. `alt_screen` is false with
+// `--no-alt-screen`, where the UI is drawn inline below the prompt instead.
+struct TerminalCleanup {
+    alt_screen: bool,
+}
+
+impl TerminalCleanup {
+    fn new(alt_screen: bool) -> Result<Self> {
+        enable_raw_mode().context("Failed to enable raw mode")?;
+        let mut tty = open_tty()?;
+        if alt_screen {
+            execute!(tty, EnterAlternateScreen).context("Failed to enter alternate screen")?;
+        }
+        execute!(tty, EnableMouseCapture).context("Failed to enable mouse capture")?;
+        Ok(Self { alt_screen })
+    }
+}
+
+impl Drop for TerminalCleanup {
+    fn drop(&mut self) {
+        restore_terminal(self.alt_screen);
+    }
+}
+
+// Function to put the terminal back into its normal state, leaving the alternate screen if
+// the UI was drawn on it. Errors are ignored since it runs during cleanup.
+pub fn restore_terminal(alt_screen: bool) {
+    let _ = disable_raw_mode();
+    if let Ok(mut tty) = open_tty() {
+        let _ = execute!(tty, DisableMouseCapture);
+        if alt_screen {
+            let _ = execute!(tty, LeaveAlternateScreen);
+        }
+    }
+}
+
+// The match browser: ripgrep output in a list beside a preview of the selected match.
+// Embedders build one from their own `Args` and config and either hand it the terminal with
+// `run`, or drive it from a loop of their own with `start`, `tick`, `render` and
+// `handle_event`.
+pub struct App {
+    args: Args,
+    keybindings: KeyBindings,
+    theme: Theme,
+    // Set while an editor or action runs in the terminal instead of the browser
+    handed_over: Arc<AtomicBool>,
+    // Wakes the event loop when a reader has news; `run` points it at its own loop
+    waker: Waker,
+    // Where matches are still being read from; `--search` starts one per query instead
+    match_source: Option<MatchSource>,
+    // When the `--search` query last changed, until the search for it is started
+    search_changed_at: Option<Instant>,
+    // `R` asked for the search or `--rg-cmd` to be run again
+    reload: bool,
+    // Path and line of the match selected before reloading, to select again once it shows up
+    reselect: Option<(String, usize)>,
+    // The position saved for this input is restored once it has been read completely
+    restore_position: bool,
+    rg_matches: Vec<RgMatch>,
+    // Totals from the summary record at the end of `rg --json` output
+    stats: Option<Stats>,
+    // Whether `--max-matches` cut the current input off
+    truncated: bool,
+    // Every match read so far, by file, for the per-file counts in the list
+    per_file: GroupedMatches,
+    // Line numbers of the context lines ripgrep reported, by file
+    context_lines: HashMap<String, HashSet<usize>>,
+    // For each entry of `rg_matches`, which match in its file it is (1-based)
+    ordinals: Vec<usize>,
+    seen_paths: HashSet<String>,
+    // When stdout is captured (e.g. `vim "$(rg --json foo | rgnav)"`), Enter confirms the
+    // selection instead of opening the editor
+    confirm_on_enter: bool,
+    // Matches opened with Enter, in this and earlier sessions, for jumping back with ctrl-o
+    history: Vec<(String, usize)>,
+    selected_idx: usize,
+    // Number of list rows visible in the last drawn frame, used for paging
+    page_size: usize,
+    // Soft-wrap preview lines instead of truncating them, toggled with `w`
+    wrap_preview: bool,
+    // Whether the preview shows a line-number gutter, toggled with `#`
+    line_numbers: bool,
+    // Lines of context around the match, adjustable at runtime with `+`/`-`
+    context: usize,
+    // Transient message shown in the status bar until the next key press
+    status_message: Option<String>,
+    bat_hint_shown: bool,
+    preview_cache: PreviewCache,
+    prefetcher: Prefetcher,
+    // Whether the preview pane is shown, toggled with `p`
+    show_preview: bool,
+    // Share of the list pane in percent, of the width or of the height when the panes are
+    // stacked, adjustable with `<`/`>`
+    list_percent: u16,
+    // Rows the preview is scrolled by for the match it was scrolled on, and the preview
+    // height from the last frame for half-page steps
+    preview_scroll: (Option<usize>, u16),
+    // Columns the unwrapped preview is scrolled right by, reset along with the rows
+    preview_column: u16,
+    // Preview lines containing the `F` text as of the last frame, which of them `n`/`N` last
+    // went to, and whether the next frame should scroll to it
+    preview_hits: Vec<usize>,
+    preview_hit: Option<usize>,
+    scroll_to_hit: bool,
+    // Scroll the next rendered preview so the matched line sits in the middle
+    center_preview: bool,
+    preview_height: u16,
+    // When the selection last changed, until its preview has been rendered
+    selection_changed_at: Option<Instant>,
+    // Whether the selection has stayed put long enough to render its preview
+    preview_settled: bool,
+    // Index into `filtered` of the first visible list row
+    list_offset: usize,
+    // Pane areas from the last frame, for mapping mouse positions
+    list_area: Rect,
+    preview_area: Rect,
+    // Whether the keybinding overlay toggled with `?` is open
+    show_help: bool,
+    // Active filters, and the prompt being typed into along with the value it started from
+    filters: Filters,
+    prompt: Option<(Prompt, String)>,
+    // How the listed matches are ordered, toggled with `s` and `r`
+    sort: SortMode,
+    reverse: bool,
+    // List only file names instead of full paths, toggled with `f`
+    basenames: bool,
+    // Whether paths are listed as given, relative or absolute, cycled with `a`
+    path_style: PathStyle,
+    cwd: Option<PathBuf>,
+    // For each entry of `rg_matches`, its path in `path_style`, resolved once rather than on
+    // every rebuild of the list
+    listed_paths: Vec<String>,
+    // Indices into `rg_matches` of the matches marked with Space
+    marked: HashSet<usize>,
+    // Digits typed so far as a count for the next key
+    pending_count: String,
+    // Directories collapsed in the tree view toggled with `t`, or `None` for the flat list
+    tree: Option<HashSet<String>>,
+    // Rows currently listed, pointing into `rg_matches`
+    filtered: Vec<ListRow>,
+    // Animates the spinner shown while loading
+    spinner_clock: Instant,
+    spinner_frame: usize,
+    // Something changed since the last frame
+    dirty: bool,
+}
+
+// How the user left the browser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    // The confirmed matches as `--output-format` lines
+    Selected(String),
+    // Quit without confirming anything
+    Quit,
+    // Left with Ctrl-C
+    Interrupted,
+}
+
+// What is left to the caller of `App::handle_event`
+pub enum Step {
+    // Keep browsing
+    Continue,
+    // Put the terminal back into its normal state, pass the job to `App::run_job`, then take
+    // the terminal back and redraw all of it
+    HandOver(Job),
+    // Stop browsing
+    Exit(Exit),
+}
+
+// A program to run in the terminal in place of the browser, see `Step::HandOver`
+pub struct Job(JobKind);
+
+enum JobKind {
+    // The `--action-cmd` template, run for each path and line in turn
+    Action(String, Vec<(PathBuf, usize)>),
+    // `$EDITOR`, opening all the files at once
+    Editor(Vec<EditorTarget>),
+}
+
+impl App {
+    // Function to set up the browser with the key bindings and theme from `config`
+    pub fn new(args: Args, config: &Config) -> Result<Self> {
+        let keybindings = KeyBindings::from_config(config)?;
+        let theme = Theme::from_config(config)?;
+        let theme = if args.no_color {
+            Theme::MONOCHROME
+        } else {
+            theme
+        };
+        let preview_cache = new_preview_cache();
+
+        Ok(Self {
+            keybindings,
+            theme,
+            handed_over: Arc::new(AtomicBool::new(false)),
+            waker: Waker::detached(),
+            match_source: None,
+            search_changed_at: None,
+            reload: false,
+            reselect: None,
+            restore_position: args.search.is_none(),
+            rg_matches: Vec::new(),
+            stats: None,
+            truncated: false,
+            per_file: GroupedMatches::default(),
+            context_lines: HashMap::new(),
+            ordinals: Vec::new(),
+            seen_paths: HashSet::new(),
+            confirm_on_enter: !atty::is(Stream::Stdout),
+            history: load_history(),
+            selected_idx: 0,
+            page_size: 1,
+            wrap_preview: false,
+            line_numbers: args.line_numbers,
+            context: args.context,
+            status_message: None,
+            bat_hint_shown: false,
+            prefetcher: Prefetcher::new(preview_cache.clone()),
+            preview_cache,
+            show_preview: true,
+            list_percent: 30,
+            preview_scroll: (None, 0),
+            preview_column: 0,
+            preview_hits: Vec::new(),
+            preview_hit: None,
+            scroll_to_hit: false,
+            center_preview: true,
+            preview_height: 1,
+            selection_changed_at: None,
+            preview_settled: true,
+            list_offset: 0,
+            list_area: Rect::default(),
+            preview_area: Rect::default(),
+            show_help: false,
+            filters: Filters::default(),
+            // `--search` starts out asking for the query
+            prompt: args
+                .search
+                .as_ref()
+                .map(|_| (Prompt::Search, String::new())),
+            sort: SortMode::Input,
+            reverse: false,
+            basenames: false,
+            path_style: args.path_style,
+            cwd: std::env::current_dir().and_then(fs::canonicalize).ok(),
+            listed_paths: Vec::new(),
+            marked: HashSet::new(),
+            pending_count: String::new(),
+            tree: None,
+            filtered: Vec::new(),
+            spinner_clock: Instant::now(),
+            spinner_frame: 0,
+            dirty: true,
+            args,
+        })
+    }
+
+    // Function to get a flag that is set while the browser has handed the terminal over to
+    // another program, which then owns it until the flag is cleared again
+    pub fn terminal_handed_over(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.handed_over)
+    }
+
+    // Function to print what gets parsed from the input instead of browsing it, as with
+    // `--debug-parse`
+    pub fn debug_parse(&self) -> Result<()> {
+        debug_parse(&self.args)
+    }
+
+    // Function to take over the terminal and browse the matches until the user confirms a
+    // selection or quits
+    pub fn run(mut self) -> Result<Exit> {
+        // Enter alternate screen and enable raw mode, undone when `cleanup` is dropped
+        let alt_screen = !self.args.no_alt_screen;
+        let mut cleanup = TerminalCleanup::new(alt_screen)?;
+        let events = Events::new(Arc::clone(&self.handed_over));
+        self.waker = events.waker();
+        self.start()?;
+        let mut terminal = setup_terminal(alt_screen)?;
+
+        let exit = loop {
+            self.tick();
+            if self.needs_redraw() {
+                terminal.draw(|f| self.render(f))?;
+            }
+
+            // Sleep until input arrives or a reader thread has news
+            let Some(event) = events.next(self.poll_timeout())? else {
+                continue;
+            };
+            if let Event::Resize(..) = event {
+                // Redraw right away; the draw recomputes page sizes and pane areas
+                terminal.autoresize()?;
+            }
+            match self.handle_event(event) {
+                Step::Continue => {}
+                // Hand the terminal over and take it back afterwards
+                Step::HandOver(job) => {
+                    let paused = events.pause();
+                    drop(cleanup);
+                    self.run_job(job);
+                    cleanup = TerminalCleanup::new(alt_screen)?;
+                    drop(paused);
+                    terminal.clear()?;
+                }
+                Step::Exit(exit) => break exit,
+            }
+        };
+
+        // Restore the terminal before printing so the selection lands on the real stdout.
+        // Inline, the UI is wiped first and the selection takes its place.
+        if !alt_screen {
+            terminal.clear()?;
+        }
+        drop(terminal);
+        drop(cleanup);
+        self.save_state();
+        Ok(exit)
+    }
+
+    // Function to start reading the matches, from the input or by running `--rg-cmd`;
+    // `--search` waits for a query instead. `run` does this itself.
+    pub fn start(&mut self) -> Result<()> {
+        let args = &self.args;
+        self.match_source = match (&args.search, &args.rg_cmd) {
+            (Some(_), _) => None,
+            (None, Some(command)) if args.input.is_none() && atty::is(Stream::Stdin) => Some(
+                spawn_rg_command(command, parse_options(args), self.waker.clone())?,
+            ),
+            _ => Some(get_rg_matches(
+                args.input.as_deref(),
+                parse_options(args),
+                self.waker.clone(),
+            )?),
+        };
+        Ok(())
+    }
+
+    // Function to catch up on everything that doesn't wait for input: start the search once
+    // its query has settled, take the records read since the last call and keep track of
+    // the selection and the spinner. Called before every frame.
+    pub fn tick(&mut self) {
+        // Start searching once the query has stopped changing for a moment, or right away
+        // when reloading
+        if self.reload
+            || self
+                .search_changed_at
+                .is_some_and(|at| at.elapsed() >= SEARCH_DEBOUNCE)
+        {
+            self.restart_search();
+        }
+
+        // Take whatever the reader thread has parsed since the last frame
+        if self.match_source.is_some() {
+            self.receive_matches();
+        }
+
+        // Scrolling is per selection, so start centered on the match whenever the selection
+        // changes
+        let current_match = self
+            .filtered
+            .get(self.selected_idx)
+            .and_then(ListRow::match_idx);
+        if self.preview_scroll.0 != current_match {
+            self.preview_scroll = (current_match, 0);
+            self.preview_column = 0;
+            self.preview_hit = None;
+            self.center_preview = true;
+            self.selection_changed_at = Some(Instant::now());
+        }
+        // Hold off rendering previews while the selection is moving quickly, then redraw
+        // once it has settled
+        self.preview_settled = self
+            .selection_changed_at
+            .is_none_or(|at| at.elapsed() >= PREVIEW_DEBOUNCE);
+        if self.preview_settled && self.selection_changed_at.take().is_some() {
+            self.dirty = true;
+        }
+
+        // Keep the spinner turning while matches load
+        let frame = (self.spinner_clock.elapsed().as_millis() / SPINNER_INTERVAL.as_millis())
+            as usize
+            % SPINNER_FRAMES.len();
+        if self.match_source.is_some() && frame != self.spinner_frame {
+            self.spinner_frame = frame;
+            self.dirty = true;
+        }
+    }
+
+    // Function to tell whether anything changed since the last `render`; idle polls skip
+    // drawing (and rendering previews)
+    pub fn needs_redraw(&self) -> bool {
+        self.dirty
+    }
+
+    // Function to get how long to wait for input before `tick` has something to do: in time
+    // to start a pending search, render a pending preview or turn the spinner. A frame still
+    // to be drawn doesn't wait at all.
+    pub fn poll_timeout(&self) -> Duration {
+        if self.dirty {
+            return Duration::ZERO;
+        }
+        let time_left = |since: Option<Instant>, delay: Duration| {
+            since.map_or(self.args.poll_interval, |at| {
+                delay.saturating_sub(at.elapsed())
+            })
+        };
+        let mut timeout = time_left(self.search_changed_at, SEARCH_DEBOUNCE)
+            .min(time_left(self.selection_changed_at, PREVIEW_DEBOUNCE));
+        if self.match_source.is_some() {
+            timeout = timeout.min(SPINNER_INTERVAL);
+        }
+        timeout
+    }
+
+    // Function to drop the current matches and run the search for the current query, or
+    // `--rg-cmd` again when reloading
+    fn restart_search(&mut self) {
+        self.search_changed_at = None;
+        // Dropping the previous source kills its rg
+        self.match_source = None;
+        self.rg_matches.clear();
+        self.ordinals.clear();
+        self.listed_paths.clear();
+        self.filtered.clear();
+        self.marked.clear();
+        self.filters.hidden.clear();
+        self.per_file = GroupedMatches::default();
+        self.context_lines.clear();
+        self.seen_paths.clear();
+        self.stats = None;
+        self.truncated = false;
+        self.selected_idx = 0;
+        let args = &self.args;
+        let source = match (args.search.as_deref(), args.rg_cmd.as_deref()) {
+            (Some(dir), _) if !self.filters.search.is_empty() => Some(spawn_rg_search(
+                &self.filters.search,
+                dir,
+                parse_options(args),
+                self.waker.clone(),
+            )),
+            (None, Some(command)) if self.reload => Some(spawn_rg_command(
+                command,
+                parse_options(args),
+                self.waker.clone(),
+            )),
+            _ => None,
+        };
+        match source {
+            Some(Ok(source)) => self.match_source = Some(source),
+            Some(Err(e)) => self.status_message = Some(format!("{:#}", e)),
+            None => {}
+        }
+        self.reload = false;
+        self.rebuild();
+        self.dirty = true;
+    }
+
+    // Function to take the records the reader thread has parsed so far, and select the
+    // match to start from once they're in
+    fn receive_matches(&mut self) {
+        let Some(source) = &self.match_source else {
+            return;
+        };
+        let mut received = false;
+        let mut finished = false;
+        loop {
+            match source.try_recv() {
+                Ok(Ok(Parsed::Summary(summary))) => {
+                    self.stats = Some(summary.stats);
+                    self.dirty = true;
+                }
+                Ok(Ok(Parsed::Truncated)) => {
+                    self.truncated = true;
+                    self.dirty = true;
+                }
+                Ok(Ok(Parsed::Context(line))) => {
+                    self.context_lines
+                        .entry(line.path.text)
+                        .or_default()
+                        .insert(line.line_number);
+                    self.dirty = true;
+                }
+                Ok(Ok(Parsed::Match(rg_match))) => {
+                    received = true;
+                    let Some(data) = rg_match.data.as_ref() else {
+                        continue;
+                    };
+                    let ordinal = self.per_file.add(data);
+                    // Only keep the first match per file when collapsing; grouping shows one
+                    // entry per file, so it implies `--unique`
+                    if (self.args.unique || self.args.group)
+                        && !self.seen_paths.insert(data.path.text.clone())
+                    {
+                        continue;
+                    }
+                    self.rg_matches.push(rg_match);
+                    self.ordinals.push(ordinal);
+                }
+                Ok(Err(e)) => self.status_message = Some(format!("{:#}", e)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished = true;
+                    self.dirty = true;
+                    break;
+                }
+            }
+        }
+        if finished {
+            self.match_source = None;
+        }
+        if received {
+            self.dirty = true;
+            style_new_paths(
+                &mut self.listed_paths,
+                &self.rg_matches,
+                self.path_style,
+                self.cwd.as_deref(),
+            );
+            self.rebuild();
+        }
+
+        // Pick up where the same input was left last time, unless the user already moved
+        // while it loaded. Only the first load is restored; reloads keep the selection.
+        if self.match_source.is_none() && self.restore_position {
+            self.restore_position = false;
+            if self.selected_idx == 0 {
+                let key = position_key(self.args.input.as_deref(), &self.rg_matches);
+                if let Some(idx) = load_position(&key, self.rg_matches.len()) {
+                    self.selected_idx = self
+                        .filtered
+                        .iter()
+                        .position(|row| row.match_idx() == Some(idx))
+                        .unwrap_or(self.selected_idx);
+                }
+            }
+        }
+
+        // Select the match from before reloading as soon as it's back, or another one in the
+        // same file once everything is read
+        if let Some((path, line_number)) = &self.reselect {
+            let loaded = self.match_source.is_none();
+            let found = listed_position(&self.rg_matches, &self.filtered, path, Some(*line_number))
+                .or_else(|| {
+                    listed_position(&self.rg_matches, &self.filtered, path, None).filter(|_| loaded)
+                });
+            if let Some(idx) = found {
+                self.selected_idx = idx;
+            }
+            if found.is_some() || loaded {
+                self.reselect = None;
+            }
+        }
+    }
+
+    // Function to list the rows again after the matches, filters, order or tree view
+    // changed, keeping the selected match selected if it's still listed
+    fn rebuild(&mut self) {
+        let location = selected_location(&self.rg_matches, &self.filtered, self.selected_idx);
+        self.filtered = list_rows(
+            &self.rg_matches,
+            &self.filters,
+            &self.listed_paths,
+            self.args.exact,
+            self.sort,
+            self.reverse,
+            self.tree.as_ref(),
+        );
+        self.selected_idx = keep_selection(
+            &self.rg_matches,
+            &self.filtered,
+            location.as_ref(),
+            self.selected_idx,
+        );
+    }
+
+    // Function to draw the list, the preview and the status bar into `f`
+    pub fn render(&mut self, f: &mut Frame) {
+        self.dirty = false;
+        let args = &self.args;
+        let theme = &self.theme;
+        // Reserve the bottom row for the status bar
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)])
+            .split(f.area());
+
+        // The list takes the full width while the preview is hidden
+        let constraints = if self.show_preview {
+            [
+                Constraint::Percentage(self.list_percent),
+                Constraint::Percentage(100 - self.list_percent),
+            ]
+        } else {
+            [Constraint::Percentage(100), Constraint::Percentage(0)]
+        };
+        // Narrow terminals leave too little room for paths beside the preview
+        let direction = match args.layout {
+            PaneLayout::Horizontal => Direction::Horizontal,
+            PaneLayout::Vertical => Direction::Vertical,
+            PaneLayout::Auto if f.area().width < args.vertical_below => Direction::Vertical,
+            PaneLayout::Auto => Direction::Horizontal,
+        };
+        let chunks = Layout::default()
+            .direction(direction)
+            .constraints(constraints.as_ref())
+            .split(rows[0]);
+
+        // Subtract the top and bottom borders from the list height
+        self.page_size = chunks[0].height.saturating_sub(2).max(1) as usize;
+
+        // Only the visible window gets turned into list items, so scroll just far enough to
+        // keep the selection on screen
+        let filtered = &self.filtered;
+        let rg_matches = &self.rg_matches;
+        let selected_idx = self.selected_idx;
+        self.list_offset = visible_offset(
+            self.list_offset,
+            selected_idx,
+            self.page_size,
+            filtered.len(),
+        );
+        let list_offset = self.list_offset;
+        let visible = &filtered[list_offset..filtered.len().min(list_offset + self.page_size)];
+
+        // Mark the characters of each path that the fuzzy filter matched
+        let query = self.filters.query.as_str();
+        let marked = &self.marked;
+        // Once anything is marked every row gets a column for the mark
+        let mark_width = if marked.is_empty() { 0 } else { MARK.width() };
+        // Grouped and path-only entries aren't about a single line. Measuring only the rows
+        // on screen keeps frames cheap however many matches there are.
+        let widths = if args.group || args.paths_only {
+            None
+        } else {
+            Some(position_widths(rg_matches, visible))
+        };
+        let position_width = widths.map_or(0, |(line, column)| line + column + 1);
+        let label_width = (chunks[0].width.saturating_sub(2) as usize)
+            .saturating_sub(mark_width + position_width);
+        let mark_column = " ".repeat(mark_width);
+        let blank_position = " ".repeat(position_width);
+        let items: Vec<ListItem> = visible
+            .iter()
+            .filter_map(|row| {
+                let (idx, depth) = match row {
+                    ListRow::Match { idx, depth } => (*idx, *depth),
+                    ListRow::Dir {
+                        name,
+                        depth,
+                        matches,
+                        collapsed,
+                        ..
+                    } => {
+                        let arrow = if *collapsed { '▸' } else { '▾' };
+                        let label = format!(
+                            "{}{}{}{} {}/ ({})",
+                            mark_column,
+                            blank_position,
+                            TREE_INDENT.repeat(*depth),
+                            arrow,
+                            name,
+                            matches
+                        );
+                        let (label, _) =
+                            fit_to_width(&label, label_width + mark_width + position_width);
+                        return Some(ListItem::new(Line::styled(label, TREE_DIR_STYLE)));
+                    }
+                };
+                let data = rg_matches[idx].data.as_ref()?;
+                // Inside the tree the directories are already shown above the file
+                let indent = TREE_INDENT.repeat(depth);
+                let path = &self.listed_paths[idx];
+                let (label, shown) = list_item_label(
+                    data,
+                    path,
+                    (self.ordinals[idx], self.per_file.count(&data.path.text)),
+                    args.group,
+                    args.paths_only,
+                    self.basenames || self.tree.is_some(),
+                    label_width.saturating_sub(indent.width()),
+                );
+                let positions = if args.exact || query.is_empty() {
+                    Vec::new()
+                } else {
+                    // Fuzzy positions index the full path, the label may show less of it
+                    fuzzy_match(query, path)
+                        .map(|(_, positions)| positions)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter_map(|pos| shown.iter().position(|&p| p == Some(pos)))
+                        .collect()
+                };
+                let mut line = highlight_chars(label, &positions);
+                if !indent.is_empty() {
+                    line.spans.insert(0, Span::raw(indent));
+                }
+                // Ahead of the tree's indentation, so the numbers line up throughout
+                if let Some(widths) = widths {
+                    line.spans.insert(
+                        0,
+                        Span::styled(position_column(data, widths), POSITION_STYLE),
+                    );
+                }
+                if marked.contains(&idx) {
+                    line.spans.insert(0, Span::styled(MARK, MARK_STYLE));
+                } else if !marked.is_empty() {
+                    line.spans.insert(0, Span::raw(mark_column.clone()));
+                }
+                // Dim matches whose file is gone since ripgrep ran
+                if !data.path.os_path().exists() {
+                    line = line.patch_style(MISSING_FILE_STYLE);
+                }
+                Some(ListItem::new(line))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(bordered(theme).title(list_title(
+                self.sort,
+                self.reverse,
+                marked.len(),
+                filter_badge(&self.filters, filtered, rg_matches.len()).as_deref(),
+            )))
+            .highlight_style(theme.selected);
+
+        let mut list_state = ListState::default().with_selected(Some(selected_idx - list_offset));
+        f.render_stateful_widget(list, chunks[0], &mut list_state);
+        self.list_area = chunks[0];
+        self.preview_area = chunks[1];
+
+        if filtered.is_empty() {
+            let message = if self.match_source.is_some() {
+                "Loading…"
+            } else {
+                "No matches"
+            };
+            render_empty_state(f, chunks[0], message);
+            if self.show_preview {
+                f.render_widget(bordered(theme).title("Code Preview"), chunks[1]);
+                render_empty_state(f, chunks[1], message);
+            }
+        }
+        // A directory heading has nothing to preview
+        if let Some(ListRow::Dir { path, .. }) =
+            filtered.get(selected_idx).filter(|_| self.show_preview)
+        {
+            f.render_widget(bordered(theme).title("Code Preview"), chunks[1]);
+            render_empty_state(f, chunks[1], path);
+        }
+
+        if let Some(data) =
+            selected_data(rg_matches, filtered, selected_idx).filter(|_| self.show_preview)
+        {
+            let options = PreviewOptions {
+                context: self.context,
+                // Scrolled sideways, long lines are kept whole so the rest is reachable
+                wrap: self.wrap_preview || self.preview_column > 0,
+                highlighter: args.highlighter,
+                max_file_size: args.max_preview_size.saturating_mul(BYTES_PER_MB),
+                line_numbers: self.line_numbers,
+                fold_markers: args.fold_markers,
+                match_line_style: theme.match_line,
+                bat_theme: args.bat_theme.clone(),
+                preview_command: args.preview_command.clone(),
+                no_color: args.no_color,
+                git_context: args.git_context,
+                follow_symlinks: args.follow_symlinks,
+            };
+            // Capture `bat` output for file preview with context around the match line.
+            // Until the selection settles only previews that are cached already show.
+            let preview = if self.preview_settled {
+                let preview = get_file_preview(&self.preview_cache, data, &options);
+                // The next move is most likely to one of the neighbours
+                let neighbours = [selected_idx.wrapping_sub(1), selected_idx + 1]
+                    .into_iter()
+                    .filter_map(|idx| selected_data(rg_matches, filtered, idx))
+                    .map(|data| (data.clone(), options.clone()))
+                    .collect();
+                self.prefetcher.prefetch(neighbours);
+                preview
+            } else {
+                Ok(cached_preview(&self.preview_cache, data, &options).unwrap_or_default())
+            };
+            let failed = preview.is_err();
+            let mut preview = preview.unwrap_or_else(|e| Preview::from(preview_error_text(&e)));
+            if let Some(lines) = self.context_lines.get(&data.path.text).filter(|_| !failed) {
+                mark_context_lines(&mut preview, data.line_number, lines, theme.context_line);
+            }
+            // Removed lines and fold markers can come before the match
+            let match_row = preview.row_of(data.line_number.max(1)).unwrap_or(0);
+            let mut preview_text = preview.text;
+            self.preview_hits = if failed {
+                Vec::new()
+            } else {
+                find_in_preview(&mut preview_text, &self.filters.preview, self.line_numbers)
+            };
+
+            let preview_height = chunks[1].height.saturating_sub(2).max(1);
+            self.preview_height = preview_height;
+            // Go to the occurrence `n`/`N` picked, or after typing to the first one from the
+            // match on
+            if self.scroll_to_hit && !self.preview_hits.is_empty() {
+                self.scroll_to_hit = false;
+                self.center_preview = false;
+                let hit = self
+                    .preview_hit
+                    .unwrap_or_else(|| {
+                        self.preview_hits
+                            .iter()
+                            .position(|&line| line >= match_row)
+                            .unwrap_or(0)
+                    })
+                    .min(self.preview_hits.len() - 1);
+                self.preview_hit = Some(hit);
+                self.preview_scroll.1 =
+                    (self.preview_hits[hit] as u16).saturating_sub(preview_height / 2);
+            }
+            if self.center_preview && !preview_text.lines.is_empty() {
+                self.center_preview = false;
+                // Near either end of the file don't scroll past the text
+                let row = match_row as u16;
+                let max_centered = (preview_text.lines.len() as u16).saturating_sub(preview_height);
+                self.preview_scroll.1 = row.saturating_sub(preview_height / 2).min(max_centered);
+            }
+            let max_scroll = preview_text.lines.len().saturating_sub(1) as u16;
+            self.preview_scroll.1 = self.preview_scroll.1.min(max_scroll);
+            // Stop once the longest line's end is in view; an empty text is just a preview
+            // that isn't cached yet
+            if !preview_text.lines.is_empty() {
+                let max_column =
+                    (preview_text.width() as u16).saturating_sub(chunks[1].width.saturating_sub(2));
+                self.preview_column = self.preview_column.min(max_column);
+            }
+
+            let block = if failed {
+                bordered(theme)
+                    .border_style(PREVIEW_ERROR_STYLE)
+                    .title("Preview Error")
+            } else if self.filters.preview.is_empty() {
+                bordered(theme).title("Code Preview")
+            } else {
+                let found = match (self.preview_hit, self.preview_hits.len()) {
+                    (_, 0) => "no matches".to_string(),
+                    (Some(hit), total) => format!("{}/{}", hit + 1, total),
+                    (None, total) => format!("{} lines", total),
+                };
+                bordered(theme).title(format!(
+                    "Code Preview [{}: {}]",
+                    self.filters.preview, found
+                ))
+            };
+            let mut preview = Paragraph::new(preview_text).block(block);
+            preview = if self.wrap_preview || failed {
+                preview
+                    .wrap(Wrap { trim: false })
+                    .scroll((self.preview_scroll.1, 0))
+            } else {
+                preview.scroll((self.preview_scroll.1, self.preview_column))
+            };
+            f.render_widget(preview, chunks[1]);
+        }
+
+        if let Some((target, _)) = self.prompt {
+            // The status bar doubles as the filter input line
+            let line = format!("{}{}", target.label(), self.filters.get(target));
+            f.set_cursor_position((rows[1].x + line.width() as u16, rows[1].y));
+            f.render_widget(Paragraph::new(line), rows[1]);
+        } else {
+            let count_message =
+                (!self.pending_count.is_empty()).then(|| format!("count: {}", self.pending_count));
+            let status = Paragraph::new(status_text(
+                selected_idx,
+                rg_matches,
+                filtered,
+                &self.filters,
+                LoadProgress {
+                    spinner: self
+                        .match_source
+                        .as_ref()
+                        .map(|_| SPINNER_FRAMES[self.spinner_frame]),
+                    truncated_at: args.max_matches.filter(|_| self.truncated),
+                },
+                self.stats.as_ref(),
+                self.status_message.as_deref().or(count_message.as_deref()),
+            ))
+            .style(theme.status_bar);
+            f.render_widget(status, rows[1]);
+        }
+
+        if self.show_help {
+            render_help(f, self.keybindings.help_entries(), theme);
+        }
+
+        // Colors can come from anywhere, including bat or a preview command, so drop them
+        // from the finished frame; bold, reverse and the like stay
+        if args.no_color {
+            for cell in f.buffer_mut().content.iter_mut() {
+                cell.set_fg(Color::Reset).set_bg(Color::Reset);
+            }
+        }
+
+        // Let the user know once why previews lost their highlighting, in the next frame
+        if !self.bat_hint_shown && BAT_MISSING.load(Ordering::Relaxed) {
+            self.bat_hint_shown = true;
+            self.status_message = Some("bat not found, showing plain previews".to_string());
+            self.dirty = true;
+        }
+    }
+
+    // Function to react to a terminal event, leaving to the caller what only it can do
+    pub fn handle_event(&mut self, event: Event) -> Step {
+        // Bare pointer motion doesn't change anything on screen
+        if !matches!(event, Event::Mouse(mouse) if mouse.kind == MouseEventKind::Moved) {
+            self.dirty = true;
+        }
+        match event {
+            Event::Mouse(mouse) => {
+                self.handle_mouse(mouse);
+                Step::Continue
+            }
+            Event::Key(key) => self.handle_key(key),
+            _ => Step::Continue,
+        }
+    }
+
+    // Function to select list rows with clicks and the scroll wheel, and scroll the preview
+    // with the wheel
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let over = |area: Rect| area.contains(Position::new(mouse.column, mouse.row));
+        let list_area = self.list_area;
+        let last_row = self.filtered.len().saturating_sub(1);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) if over(list_area) => {
+                // Rows start below the top border, shifted by how far the list scrolled;
+                // clicks on either border select nothing
+                let inside =
+                    mouse.row > list_area.y && mouse.row + 1 < list_area.y + list_area.height;
+                let row = mouse.row.saturating_sub(list_area.y + 1) as usize;
+                let idx = self.list_offset + row;
+                if inside && idx < self.filtered.len() {
+                    self.selected_idx = idx;
+                }
+            }
+            MouseEventKind::ScrollDown if over(self.preview_area) => {
+                self.preview_scroll.1 = self.preview_scroll.1.saturating_add(MOUSE_SCROLL_LINES);
+            }
+            MouseEventKind::ScrollUp if over(self.preview_area) => {
+                self.preview_scroll.1 = self.preview_scroll.1.saturating_sub(MOUSE_SCROLL_LINES);
+            }
+            MouseEventKind::ScrollDown if over(list_area) => {
+                self.selected_idx = (self.selected_idx + 1).min(last_row);
+            }
+            MouseEventKind::ScrollUp if over(list_area) => {
+                self.selected_idx = self.selected_idx.saturating_sub(1);
+            }
+            _ => {}
+        }
+    }
+
+    // Function to edit the open prompt with a key, or run the action bound to it
+    fn handle_key(&mut self, key: KeyEvent) -> Step {
+        self.status_message = None;
+
+        // Raw mode turns Ctrl-C into a key press, so treat it like the SIGINT it would have
+        // been
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Step::Exit(Exit::Interrupted);
+        }
+
+        // While a filter prompt is open keys edit its value instead of navigating. Filters
+        // apply as they're typed.
+        if let Some((target, previous)) = self.prompt.take() {
+            let value = self.filters.get_mut(target);
+            match key.code {
+                KeyCode::Enter => {}
+                // Restore the filter that was active before the prompt opened
+                KeyCode::Esc => *value = previous,
+                KeyCode::Backspace => {
+                    value.pop();
+                    self.prompt = Some((target, previous));
+                }
+                KeyCode::Char(c) => {
+                    value.push(c);
+                    self.prompt = Some((target, previous));
+                }
+                _ => self.prompt = Some((target, previous)),
+            }
+            match target {
+                Prompt::Search => self.search_changed_at = Some(Instant::now()),
+                Prompt::PreviewSearch => {
+                    self.preview_hit = None;
+                    self.scroll_to_hit = true;
+                }
+                _ => {}
+            }
+            self.rebuild();
+            return Step::Continue;
+        }
+
+        // Digits build up a count for the next key, vim-style; Esc drops it
+        if !self.show_help && key.modifiers.difference(KeyModifiers::SHIFT).is_empty() {
+            match key.code {
+                KeyCode::Char(c @ '0'..='9') if c != '0' || !self.pending_count.is_empty() => {
+                    self.pending_count.push(c);
+                    return Step::Continue;
+                }
+                KeyCode::Esc if !self.pending_count.is_empty() => {
+                    self.pending_count.clear();
+                    return Step::Continue;
+                }
+                _ => {}
+            }
+        }
+
+        let action = self.keybindings.handle(key);
+
+        // The help overlay swallows keys until it's dismissed
+        if self.show_help {
+            if matches!(action, Some(Action::Help | Action::Quit)) {
+                self.show_help = false;
+            }
+            return Step::Continue;
+        }
+
+        // The count applies to whichever action the key sequence completes
+        let count = if action.is_some() {
+            std::mem::take(&mut self.pending_count)
+                .parse::<usize>()
+                .ok()
+        } else {
+            None
+        };
+        let last_row = self.filtered.len().saturating_sub(1);
+        // Jump to the counted result, 1-based
+        let jump_to = count.map(|n| (n - 1).min(last_row));
+
+        match action {
+            Some(Action::Up) if count.is_some() => {
+                self.selected_idx = self.selected_idx.saturating_sub(count.unwrap_or(1));
+            }
+            Some(Action::Down) if count.is_some() => {
+                self.selected_idx = (self.selected_idx + count.unwrap_or(1)).min(last_row);
+            }
+            Some(Action::Up) => {
+                if self.selected_idx > 0 {
+                    self.selected_idx -= 1;
+                } else if self.args.wrap_around {
+                    self.selected_idx = last_row;
+                }
+            }
+            Some(Action::Down) => {
+                if self.selected_idx + 1 < self.filtered.len() {
+                    self.selected_idx += 1;
+                } else if self.args.wrap_around {
+                    self.selected_idx = 0;
+                }
+            }
+            Some(Action::Open | Action::Top | Action::Bottom) if jump_to.is_some() => {
+                self.selected_idx = jump_to.unwrap_or(self.selected_idx);
+            }
+            Some(Action::PageDown) => {
+                self.selected_idx = (self.selected_idx + self.page_size).min(last_row);
+            }
+            Some(Action::PageUp) => {
+                self.selected_idx = self.selected_idx.saturating_sub(self.page_size);
+            }
+            Some(Action::Top) => self.selected_idx = 0,
+            Some(Action::Bottom) => self.selected_idx = last_row,
+            Some(Action::NextFile) => {
+                self.selected_idx = next_file(&self.rg_matches, &self.filtered, self.selected_idx);
+            }
+            Some(Action::PreviousFile) => {
+                self.selected_idx =
+                    previous_file(&self.rg_matches, &self.filtered, self.selected_idx);
+            }
+            Some(Action::ToggleWrap) => {
+                self.wrap_preview = !self.wrap_preview;
+                self.center_preview = true;
+            }
+            Some(Action::ToggleLineNumbers) => self.line_numbers = !self.line_numbers,
+            Some(Action::TogglePreview) => self.show_preview = !self.show_preview,
+            Some(Action::ScrollPreviewDown) => {
+                self.preview_scroll.1 = self
+                    .preview_scroll
+                    .1
+                    .saturating_add((self.preview_height / 2).max(1));
+            }
+            Some(Action::ScrollPreviewUp) => {
+                self.preview_scroll.1 = self
+                    .preview_scroll
+                    .1
+                    .saturating_sub((self.preview_height / 2).max(1));
+            }
+            // Wrapped lines have nothing to the right to scroll to
+            Some(Action::ScrollPreviewRight) if !self.wrap_preview => {
+                self.preview_column = self.preview_column.saturating_add(HORIZONTAL_SCROLL_STEP);
+            }
+            Some(Action::ScrollPreviewLeft) => {
+                self.preview_column = self.preview_column.saturating_sub(HORIZONTAL_SCROLL_STEP);
+            }
+            Some(Action::ShrinkList) => {
+                self.list_percent = self
+                    .list_percent
+                    .saturating_sub(SPLIT_STEP)
+                    .max(MIN_LIST_PERCENT);
+            }
+            Some(Action::GrowList) => {
+                self.list_percent = (self.list_percent + SPLIT_STEP).min(MAX_LIST_PERCENT);
+            }
+            Some(Action::MoreContext) => {
+                self.context += 1;
+                self.center_preview = true;
+            }
+            Some(Action::LessContext) => {
+                self.context = self.context.saturating_sub(1);
+                self.center_preview = true;
+            }
+            Some(action @ (Action::CopyPath | Action::CopyLocation | Action::CopyLine)) => {
+                if let Some(data) =
+                    selected_data(&self.rg_matches, &self.filtered, self.selected_idx)
+                {
+                    let text = match action {
+                        Action::CopyLocation => format!("{}:{}", data.path.text, data.line_number),
+                        Action::CopyLine => {
+                            data.lines.text.trim_end_matches(['\n', '\r']).to_string()
+                        }
+                        _ => data.path.text.clone(),
+                    };
+                    // Lines that aren't valid UTF-8 come as `bytes`, leaving no text
+                    self.status_message = Some(if text.is_empty() {
+                        "No matched text to copy".to_string()
+                    } else {
+                        match copy_to_clipboard(&text, self.args.clipboard) {
+                            Ok(()) => format!("Copied {}", text),
+                            Err(e) => format!("Copy failed: {}", e),
+                        }
+                    });
+                }
+            }
+            Some(Action::OpenDirectory) => {
+                if let Some(data) =
+                    selected_data(&self.rg_matches, &self.filtered, self.selected_idx)
+                {
+                    let opened =
+                        data.path
+                            .target_path(self.args.follow_symlinks)
+                            .and_then(|path| {
+                                let dir = parent_dir(&path);
+                                open_directory(&dir).map(|()| dir)
+                            });
+                    self.status_message = Some(match opened {
+                        Ok(dir) => format!("Opened {}", dir.display()),
+                        Err(e) => format!("{:#}", e),
+                    });
+                }
+            }
+            // On a directory heading of the tree view, Enter and Space fold it
+            Some(Action::Open | Action::ToggleMark)
+                if matches!(
+                    self.filtered.get(self.selected_idx),
+                    Some(ListRow::Dir { .. })
+                ) =>
+            {
+                if let (Some(collapsed), Some(ListRow::Dir { path, .. })) =
+                    (self.tree.as_mut(), self.filtered.get(self.selected_idx))
+                {
+                    if !collapsed.remove(path) {
+                        collapsed.insert(path.clone());
+                    }
+                }
+                self.rebuild();
+            }
+            // Take the selection out of the list, leaving the cursor on the row that moves up
+            // into its place
+            Some(Action::Hide) => {
+                if let Some(idx) = self
+                    .filtered
+                    .get(self.selected_idx)
+                    .and_then(ListRow::match_idx)
+                {
+                    self.filters.hidden.push(idx);
+                    self.marked.remove(&idx);
+                    self.rebuild();
+                    self.status_message =
+                        Some(format!("{} hidden, u to undo", self.filters.hidden.len()));
+                }
+            }
+            Some(Action::Unhide) => match self.filters.hidden.pop() {
+                Some(_) => self.rebuild(),
+                None => self.status_message = Some("Nothing hidden to bring back".to_string()),
+            },
+            Some(Action::ToggleTree) => {
+                self.tree = match self.tree {
+                    Some(_) => None,
+                    None => Some(HashSet::new()),
+                };
+                self.rebuild();
+            }
+            Some(action @ (Action::Open | Action::Confirm)) => return self.open_selection(action),
+            // Walk back through the opened matches, skipping the one already selected
+            Some(Action::JumpBack) => {
+                let current =
+                    selected_location(&self.rg_matches, &self.filtered, self.selected_idx);
+                while self.history.last().is_some() && self.history.last() == current.as_ref() {
+                    self.history.pop();
+                }
+                self.status_message = Some(match self.history.pop() {
+                    Some((path, line)) => {
+                        match listed_position(&self.rg_matches, &self.filtered, &path, Some(line)) {
+                            Some(idx) => {
+                                self.selected_idx = idx;
+                                format!("Back to {}:{}", path, line)
+                            }
+                            None => format!("{}:{} is no longer listed", path, line),
+                        }
+                    }
+                    None => "No earlier opened match".to_string(),
+                });
+            }
+            Some(Action::ExportQuickfix) => {
+                let quickfix = quickfix_list(&self.rg_matches, &self.filtered);
+                match &self.args.quickfix_out {
+                    Some(path) => {
+                        self.status_message = Some(match fs::write(path, quickfix + "\n") {
+                            Ok(()) => {
+                                format!(
+                                    "Wrote {} matches to {}",
+                                    self.filtered.iter().filter_map(ListRow::match_idx).count(),
+                                    path
+                                )
+                            }
+                            Err(e) => format!("Failed to write {}: {}", path, e),
+                        });
+                    }
+                    None => return Step::Exit(Exit::Selected(quickfix)),
+                }
+            }
+            Some(Action::ToggleMark) => {
+                if let Some(idx) = self
+                    .filtered
+                    .get(self.selected_idx)
+                    .and_then(ListRow::match_idx)
+                {
+                    if !self.marked.remove(&idx) {
+                        self.marked.insert(idx);
+                    }
+                }
+            }
+            Some(Action::Reverse | Action::CycleSort) => {
+                if action == Some(Action::Reverse) {
+                    self.reverse = !self.reverse;
+                } else {
+                    self.sort = self.sort.next();
+                }
+                self.rebuild();
+            }
+            Some(Action::ToggleBasename) => self.basenames = !self.basenames,
+            Some(Action::CyclePathStyle) => {
+                self.path_style = self.path_style.next();
+                self.status_message = Some(format!("Paths: {}", self.path_style.label()));
+                self.listed_paths.clear();
+                style_new_paths(
+                    &mut self.listed_paths,
+                    &self.rg_matches,
+                    self.path_style,
+                    self.cwd.as_deref(),
+                );
+                // The query matched the paths as they were listed before
+                if !self.filters.query.is_empty() {
+                    self.rebuild();
+                }
+            }
+            Some(Action::Reload) => {
+                if self.args.search.is_some() || self.args.rg_cmd.is_some() {
+                    self.reload = true;
+                    self.restore_position = false;
+                    self.reselect =
+                        selected_location(&self.rg_matches, &self.filtered, self.selected_idx);
+                } else {
+                    self.status_message =
+                        Some("Nothing to rerun, pass the command with --rg-cmd".into());
+                }
+            }
+            Some(Action::Help) => self.show_help = true,
+            Some(Action::Filter) => {
+                self.prompt = Some((Prompt::Filter, self.filters.query.clone()));
+            }
+            Some(Action::FilterExtension) => {
+                self.prompt = Some((Prompt::Extension, self.filters.extensions.clone()));
+            }
+            Some(Action::FindInPreview) => {
+                self.prompt = Some((Prompt::PreviewSearch, self.filters.preview.clone()));
+            }
+            Some(action @ (Action::NextPreviewHit | Action::PreviousPreviewHit))
+                if !self.preview_hits.is_empty() =>
+            {
+                let total = self.preview_hits.len();
+                self.preview_hit = Some(match (self.preview_hit, action) {
+                    (None, _) => 0,
+                    (Some(hit), Action::NextPreviewHit) => (hit + 1) % total,
+                    (Some(hit), _) => (hit + total - 1) % total,
+                });
+                self.scroll_to_hit = true;
+            }
+            Some(Action::Search) if self.args.search.is_some() => {
+                self.prompt = Some((Prompt::Search, self.filters.search.clone()));
+            }
+            Some(Action::Quit) => return Step::Exit(Exit::Quit),
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    // Function to open the marked matches, or the selected one, or to confirm them when
+    // `action` is the confirm key or the output is captured
+    fn open_selection(&mut self, action: Action) -> Step {
+        // Marked matches are opened together, in input order, instead of the selected one
+        let targets: Vec<&MatchData> = if self.marked.is_empty() {
+            selected_data(&self.rg_matches, &self.filtered, self.selected_idx)
+                .into_iter()
+                .collect()
+        } else {
+            let mut indices: Vec<usize> = self.marked.iter().copied().collect();
+            indices.sort_unstable();
+            indices
+                .into_iter()
+                .filter_map(|idx| self.rg_matches[idx].data.as_ref())
+                .collect()
+        };
+        if targets.is_empty() {
+            return Step::Continue;
+        }
+        // Enter only confirms when the output is captured, the confirm key always does
+        if action == Action::Confirm || self.confirm_on_enter {
+            let lines: Vec<String> = targets
+                .iter()
+                .map(|data| format_selection(data, self.args.output_format))
+                .collect();
+            return Step::Exit(Exit::Selected(lines.join("\n")));
+        }
+        // With `--follow-symlinks` a dangling link has nothing to open
+        let paths = match targets
+            .iter()
+            .map(|data| data.path.target_path(self.args.follow_symlinks))
+            .collect::<Result<Vec<_>>>()
+        {
+            Ok(paths) => paths,
+            Err(e) => {
+                self.status_message = Some(format!("{:#}", e));
+                return Step::Continue;
+            }
+        };
+        for data in &targets {
+            let entry = (data.path.text.clone(), data.line_number);
+            if self.history.last() != Some(&entry) {
+                self.history.push(entry);
+            }
+        }
+
+        // A custom action runs once per match, also with the terminal handed over
+        if let Some(template) = &self.args.action_cmd {
+            let lines = targets.iter().map(|data| data.line_number);
+            return Step::HandOver(Job(JobKind::Action(
+                template.clone(),
+                paths.into_iter().zip(lines).collect(),
+            )));
+        }
+
+        // Each file opens once, at its first marked line
+        let mut opened = HashSet::new();
+        let files: Vec<EditorTarget> = targets
+            .iter()
+            .zip(paths)
+            .filter(|(data, _)| opened.insert(&data.path.text))
+            .map(|(data, path)| EditorTarget {
+                path,
+                line: data.line_number,
+                column: data.column(),
+                byte_column: data.byte_column(),
+            })
+            .collect();
+        // Inside tmux the editor can get a pane of its own instead of this one
+        if self.args.tmux != TmuxPane::Off && std::env::var_os("TMUX").is_some() {
+            if let Err(e) = open_in_tmux(&files, self.args.tmux) {
+                self.status_message = Some(format!("{:#}", e));
+            }
+            return Step::Continue;
+        }
+        Step::HandOver(Job(JobKind::Editor(files)))
+    }
+
+    // Function to run a job `handle_event` handed over and wait for it. The terminal has to
+    // be in its normal state meanwhile, with nothing else reading from it. How the job
+    // exited is shown in the status bar.
+    pub fn run_job(&mut self, job: Job) {
+        self.status_message = match job.0 {
+            // Stops at the first run that fails, and reports how the last run exited
+            JobKind::Action(template, targets) => {
+                let mut result = None;
+                for (path, line) in &targets {
+                    result = Some(run_action(&template, path, *line));
+                    if !matches!(result, Some(Ok(status)) if status.success()) {
+                        break;
+                    }
+                }
+                result.map(|result| match result {
+                    Ok(status) => format!("Action exited with {}", status),
+                    Err(e) => format!("{:#}", e),
+                })
+            }
+            // Say why nothing happened when the editor couldn't start or failed
+            JobKind::Editor(files) => match open_in_editor(&files) {
+                Ok(status) if status.success() => None,
+                Ok(status) => Some(format!("Editor exited with {}", status)),
+                Err(e) => Some(format!("{:#}", e)),
+            },
+        };
+        self.dirty = true;
+    }
+
+    // Function to save the history of opened matches and, once the input is fully read, the
+    // position in it for the next time it's browsed. Failures are printed to stderr, so the
+    // terminal should be restored first.
+    pub fn save_state(&self) {
+        if let Err(e) = save_history(&self.history) {
+            eprintln!("Failed to save the history: {:#}", e);
+        }
+        if self.args.search.is_none() && self.match_source.is_none() {
+            if let Some(idx) = self
+                .filtered
+                .get(self.selected_idx)
+                .and_then(ListRow::match_idx)
+            {
+                let key = position_key(self.args.input.as_deref(), &self.rg_matches);
+                if let Err(e) = save_position(&key, self.rg_matches.len(), idx) {
+                    eprintln!("Failed to save the position: {:#}", e);
+                }
+            }
+        }
+    }
+}
+
+// Function to read the input like the UI would and print a line for each record parsed from
+// it, for finding out why matches don't show up
+fn debug_parse(args: &Args) -> Result<()> {
+    let waker = Waker::detached();
+    let source = match &args.rg_cmd {
+        Some(command) if args.input.is_none() && atty::is(Stream::Stdin) => {
+            spawn_rg_command(command, parse_options(args), waker)?
+        }
+        _ => get_rg_matches(args.input.as_deref(), parse_options(args), waker)?,
+    };
+
+    let mut matches = 0;
+    while let Some(record) = source.recv() {
+        match record {
+            Ok(Parsed::Match(rg_match)) => match rg_match.data {
+                Some(data) => {
+                    matches += 1;
+                    println!(
+                        "match    {}:{} ({} submatches, {} lines)",
+                        data.path.text,
+                        data.location(),
+                        data.submatches.len(),
+                        data.spanned_lines()
+                    );
+                }
+                None => println!("match    without data"),
+            },
+            Ok(Parsed::Context(line)) => {
+                println!("context  {}:{}", line.path.text, line.line_number)
+            }
+            Ok(Parsed::Summary(summary)) => println!("summary  {}", summary.stats.describe()),
+            Ok(Parsed::Truncated) => println!("stopped  after {} matches", matches),
+            Err(e) => println!("error    {:#}", e),
+        }
+    }
+    println!("{} matches parsed", matches);
+    Ok(())
+}
+
+// Function to get how the readers parse the input, from `--max-matches` and
+// `--field-separator`
+fn parse_options(args: &Args) -> ParseOptions {
+    ParseOptions {
+        max_matches: args.max_matches,
+        field_separator: args.field_separator.clone(),
+    }
+}
+
+fn setup_terminal(alt_screen: bool) -> Result<Terminal<CrosstermBackend<File>>> {
+    let backend = CrosstermBackend::new(open_tty()?);
+    let viewport = if alt_screen {
+        Viewport::Fullscreen
+    } else {
+        Viewport::Inline(INLINE_HEIGHT)
+    };
+    let terminal = Terminal::with_options(backend, TerminalOptions { viewport })?;
+    Ok(terminal)
+}
+
+// Function to open files in `$EDITOR` (falling back to `vi`) and wait for it to exit
+fn open_in_editor(files: &[EditorTarget]) -> Result<ExitStatus> {
+    let (program, args) = editor_command(files);
+
+    // stdin carries the ripgrep output, so give the editor the real terminal
+    let tty = open_tty()?;
+
+    Command::new(&program)
+        .args(args)
+        .stdin(Stdio::from(tty))
+        .status()
+        .with_context(|| format!("Failed to launch editor `{}`", program))
+}
+
+// Function to open files in `$EDITOR` in a new tmux pane or window, without waiting for it.
+// The editor starts in rgnav's directory so relative paths still point at the files.
+fn open_in_tmux(files: &[EditorTarget], pane: TmuxPane) -> Result<()> {
+    let (program, args) = editor_command(files);
+    let subcommand = match pane {
+        TmuxPane::Window => "new-window",
+        TmuxPane::Split | TmuxPane::Off => "split-window",
+    };
+    let cwd = std::env::current_dir().context("Failed to get the current directory")?;
+    let output = Command::new("tmux")
+        .arg(subcommand)
+        .arg("-c")
+        .arg(cwd)
+        .arg(&program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run `tmux`")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "tmux {} failed: {}",
+            subcommand,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+// Function to get the program from `$EDITOR` (falling back to `vi`) along with the
+// arguments opening `files` in it, including any given in `$EDITOR` itself
+fn editor_command(files: &[EditorTarget]) -> (String, Vec<OsString>) {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi").to_string();
+
+    let editor_name = Path::new(&program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&program);
+    let mut args: Vec<OsString> = parts.map(OsString::from).collect();
+    args.extend(editor_args(editor_name, files));
+    (program, args)
+}
+
+// Function to run the `--action-cmd` template for a match through `sh` and wait for it.
+// The path and line are passed as positional parameters, so any file name is safe to use.
+fn run_action(template: &str, file: &Path, line: usize) -> Result<ExitStatus> {
+    let tty = open_tty()?;
+    Command::new("sh")
+        .args(["-c", &action_script(template), "sh"])
+        .arg(file)
+        .arg(line.to_string())
+        .stdin(Stdio::from(tty))
+        .status()
+        .with_context(|| format!("Failed to run `{}`", template))
+}
+
+// Function to turn an `--action-cmd` template into a script reading the path and line from
+// `$1` and `$2`. A placeholder the template already quotes (`'{file}'`, `"{file}"`) loses
+// those quotes, since `'"$1"'` would pass the literal text `"$1"`.
+fn action_script(template: &str) -> String {
+    let mut script = template.to_string();
+    for (placeholder, parameter) in [("{file}", "\"$1\""), ("{line}", "\"$2\"")] {
+        for quote in ['\'', '"'] {
+            let quoted = format!("{}{}{}", quote, placeholder, quote);
+            script = script.replace(&quoted, parameter);
+        }
+        script = script.replace(placeholder, parameter);
+    }
+    script
+}
+
+// Function to get the directory containing `path`, `.` for a bare file name
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// Function to show `dir` in the system file manager without waiting for it to close
+fn open_directory(dir: &Path) -> Result<()> {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let mut child = Command::new(program)
+        .arg(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run `{}`", program))?;
+    // Reap the opener once it exits so it doesn't linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+// A file to open in the editor, with the line and column to put the cursor on
+struct EditorTarget {
+    path: PathBuf,
+    line: usize,
+    // Counted in characters, as most editors take it
+    column: Option<usize>,
+    // Counted in bytes, for vim
+    byte_column: Option<usize>,
+}
+
+// Function to build the editor arguments that open each file at its line, and at the
+// match's column for editors that take one. Line 0 means ripgrep didn't report a line, so
+// that file just opens at the top.
+fn editor_args(editor_name: &str, files: &[EditorTarget]) -> Vec<OsString> {
+    match editor_name {
+        "code" | "code-insiders" | "codium" => {
+            let mut args = vec![OsString::from("--goto")];
+            for file in files {
+                let mut location = OsString::from(&file.path);
+                if file.line > 0 {
+                    location.push(format!(":{}", file.line));
+                    if let Some(column) = file.column {
+                        location.push(format!(":{}", column));
+                    }
+                }
+                args.push(location);
+            }
+            args
+        }
+        // The rest take the position as a `+` argument, which applies to the first file
+        _ => {
+            let mut args = Vec::new();
+            if let Some(file) = files.first().filter(|file| file.line > 0) {
+                let line = file.line;
+                let position = match (editor_name, file.column, file.byte_column) {
+                    ("vi" | "vim" | "nvim" | "gvim", _, Some(column)) => {
+                        format!("+call cursor({}, {})", line, column)
+                    }
+                    ("nano", Some(column), _) => format!("+{},{}", line, column),
+                    ("emacs" | "emacsclient", Some(column), _) => {
+                        format!("+{}:{}", line, column)
+                    }
+                    _ => format!("+{}", line),
+                };
+                args.push(position.into());
+            }
+            args.extend(files.iter().map(|file| file.path.clone().into()));
+            args
+        }
+    }
+}
+
+// Marker in front of matches marked with Space
+const MARK: &str = "● ";
+const MARK_STYLE: Style = Style::new().fg(Color::Magenta).add_modifier(Modifier::BOLD);
+
+// Style of the line:col column in the result list
+const POSITION_STYLE: Style = Style::new().fg(Color::Green);
+
+// Style of list entries whose file no longer exists
+const MISSING_FILE_STYLE: Style = Style::new().fg(Color::DarkGray);
+
+// Style of the characters matched by the fuzzy filter in the result list
+const FUZZY_MATCH_STYLE: Style = Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+
+// Function to format the listed matches, in list order, as `path:line:col: text` lines for
+// vim's quickfix list. The column is the byte offset of the first submatch, or 1.
+fn quickfix_list(rg_matches: &[RgMatch], filtered: &[ListRow]) -> String {
+    filtered
+        .iter()
+        .filter_map(|row| rg_matches[row.match_idx()?].data.as_ref())
+        .map(|data| {
+            format!(
+                "{}:{}:{}: {}",
+                data.path.text,
+                data.line_number,
+                data.byte_column().unwrap_or(1),
+                data.lines.text.trim_end()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// A confirmed selection as printed by `--output-format json`
+#[derive(Serialize)]
+struct SelectionRecord<'a> {
+    path: &'a str,
+    line_number: usize,
+    // Left out for plain input, which doesn't carry it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    absolute_offset: Option<u64>,
+}
+
+// Function to format a confirmed match for printing on exit
+fn format_selection(data: &MatchData, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Path => data.path.text.clone(),
+        OutputFormat::PathLine => format!("{}:{}", data.path.text, data.line_number),
+        OutputFormat::Json => serde_json::to_string(&SelectionRecord {
+            path: &data.path.text,
+            line_number: data.line_number,
+            absolute_offset: data.absolute_offset,
+        })
+        .expect("a path and numbers always serialize"),
+        OutputFormat::Vimgrep => format!(
+            "{}:{}:{}:{}",
+            data.path.text,
+            data.line_number,
+            data.byte_column().unwrap_or(1),
+            data.lines.text.trim_end()
+        ),
+    }
+}
+
+// Function to get the list row of the first match after `idx` in a different file than the
+// one at `idx`, staying put on the last file. Directory headings of the tree view are
+// skipped.
+fn next_file(rg_matches: &[RgMatch], filtered: &[ListRow], idx: usize) -> usize {
+    let path_at = |idx| selected_data(rg_matches, filtered, idx).map(|data| &data.path.text);
+    let current = path_at(idx);
+    (idx + 1..filtered.len())
+        .find(|&i| path_at(i).is_some() && path_at(i) != current)
+        .unwrap_or(idx)
+}
+
+// Function to get the list row of the first match of the file listed before the one at
+// `idx`. Files are runs of consecutive rows with the same path; directory headings of the
+// tree view are skipped.
+fn previous_file(rg_matches: &[RgMatch], filtered: &[ListRow], idx: usize) -> usize {
+    let path_at = |idx| selected_data(rg_matches, filtered, idx).map(|data| &data.path.text);
+    let group_start = |mut idx: usize| {
+        while idx > 0 && path_at(idx - 1) == path_at(idx) {
+            idx -= 1;
+        }
+        idx
+    };
+    let start = group_start(idx);
+    match (0..start).rev().find(|&i| path_at(i).is_some()) {
+        Some(previous) => group_start(previous),
+        None => start,
+    }
+}
+
+// Function to get the match behind the selected list row
+fn selected_data<'a>(
+    rg_matches: &'a [RgMatch],
+    filtered: &[ListRow],
+    selected_idx: usize,
+) -> Option<&'a MatchData> {
+    filtered
+        .get(selected_idx)
+        .and_then(ListRow::match_idx)
+        .and_then(|idx| rg_matches[idx].data.as_ref())
+}
+
+// Function to get the file and line of the selected match, which identify it across
+// changes to the list
+fn selected_location(
+    rg_matches: &[RgMatch],
+    filtered: &[ListRow],
+    selected_idx: usize,
+) -> Option<(String, usize)> {
+    selected_data(rg_matches, filtered, selected_idx)
+        .map(|data| (data.path.text.clone(), data.line_number))
+}
+
+// Function to find the row listing the match in `path` at `line_number`, or any match in
+// that file when no line is given
+fn listed_position(
+    rg_matches: &[RgMatch],
+    filtered: &[ListRow],
+    path: &str,
+    line_number: Option<usize>,
+) -> Option<usize> {
+    filtered.iter().position(|row| {
+        let data = row
+            .match_idx()
+            .and_then(|idx| rg_matches[idx].data.as_ref());
+        data.is_some_and(|data| {
+            data.path.text == path && line_number.is_none_or(|line| data.line_number == line)
+        })
+    })
+}
+
+// Function to pick the row to select after the list was rebuilt: the previously selected
+// match if it's still listed, otherwise the row closest to the old position
+fn keep_selection(
+    rg_matches: &[RgMatch],
+    filtered: &[ListRow],
+    location: Option<&(String, usize)>,
+    selected_idx: usize,
+) -> usize {
+    location
+        .and_then(|(path, line)| listed_position(rg_matches, filtered, path, Some(*line)))
+        .unwrap_or_else(|| selected_idx.min(filtered.len().saturating_sub(1)))
+}
+
+// Order in which the listed matches are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortMode {
+    // Input order, or best fuzzy match first while filtering
+    Input,
+    // Alphabetically by path, then by line number
+    Path,
+    // By line number, keeping input order among equal lines
+    LineNumber,
+}
+
+impl SortMode {
+    // Function to get the mode `s` switches to next
+    fn next(self) -> Self {
+        match self {
+            SortMode::Input => SortMode::Path,
+            SortMode::Path => SortMode::LineNumber,
+            SortMode::LineNumber => SortMode::Input,
+        }
+    }
+}
+
+// Function to build the list title, noting any sort order other than the default and how
+// many matches are marked
+fn list_title(sort: SortMode, reverse: bool, marked: usize, badge: Option<&str>) -> String {
+    let mut title = "Search Results".to_string();
+    let order = match (sort, reverse) {
+        (SortMode::Input, false) => None,
+        (SortMode::Input, true) => Some("reversed"),
+        (SortMode::Path, false) => Some("by path"),
+        (SortMode::Path, true) => Some("by path, reversed"),
+        (SortMode::LineNumber, false) => Some("by line"),
+        (SortMode::LineNumber, true) => Some("by line, reversed"),
+    };
+    if let Some(order) = order {
+        title.push_str(&format!(" ({})", order));
+    }
+    if marked > 0 {
+        title.push_str(&format!(" [{} marked]", marked));
+    }
+    if let Some(badge) = badge {
+        title.push(' ');
+        title.push_str(badge);
+    }
+    title
+}
+
+// Function to describe how far the active filters narrowed the list, e.g.
+// `42/1200 (filter: "foo")`, or `None` while nothing is filtered. Matches inside collapsed
+// directories still count as shown.
+fn filter_badge(filters: &Filters, rows: &[ListRow], total: usize) -> Option<String> {
+    let mut active = Vec::new();
+    if !filters.query.is_empty() {
+        active.push(format!("filter: {:?}", filters.query));
+    }
+    if !filters.extensions.is_empty() {
+        active.push(format!("extension: {:?}", filters.extensions));
+    }
+    if active.is_empty() {
+        return None;
+    }
+    let shown: usize = rows
+        .iter()
+        .map(|row| match row {
+            ListRow::Match { .. } => 1,
+            ListRow::Dir {
+                matches,
+                collapsed: true,
+                ..
+            } => *matches,
+            ListRow::Dir { .. } => 0,
+        })
+        .sum();
+    Some(format!("{}/{} ({})", shown, total, active.join(", ")))
+}
+
+// Filters narrowing down the listed matches; all of them have to pass. Also holds the text
+// searched for in the preview, which is typed the same way but leaves the list alone.
+#[derive(Debug, Default)]
+struct Filters {
+    // `/` query, fuzzy matched against paths unless `--exact`
+    query: String,
+    // `e` comma-separated file extensions, with or without the leading dot
+    extensions: String,
+    // `--search` query, which reruns rg rather than filtering the current matches
+    search: String,
+    // `F` text highlighted in the preview
+    preview: String,
+    // Matches taken out of the list with `d`, most recent last for `u`
+    hidden: Vec<usize>,
+}
+
+impl Filters {
+    // Function to get the filter a prompt edits
+    fn get(&self, prompt: Prompt) -> &str {
+        match prompt {
+            Prompt::Filter => &self.query,
+            Prompt::Extension => &self.extensions,
+            Prompt::Search => &self.search,
+            Prompt::PreviewSearch => &self.preview,
+        }
+    }
+
+    fn get_mut(&mut self, prompt: Prompt) -> &mut String {
+        match prompt {
+            Prompt::Filter => &mut self.query,
+            Prompt::Extension => &mut self.extensions,
+            Prompt::Search => &mut self.search,
+            Prompt::PreviewSearch => &mut self.preview,
+        }
+    }
+
+    // Function to check a path against the extension filter
+    fn has_extension(&self, path: &str) -> bool {
+        let mut wanted = self
+            .extensions
+            .split(',')
+            .map(|ext| ext.trim().trim_start_matches('.'))
+            .filter(|ext| !ext.is_empty())
+            .peekable();
+        if wanted.peek().is_none() {
+            return true;
+        }
+        let extension = Path::new(path).extension().and_then(|ext| ext.to_str());
+        wanted.any(|ext| extension == Some(ext))
+    }
+}
+
+// Filter being edited in the status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    Filter,
+    Extension,
+    Search,
+    PreviewSearch,
+}
+
+impl Prompt {
+    // Function to get the text shown in front of the value being typed
+    fn label(self) -> &'static str {
+        match self {
+            Prompt::Filter => "/",
+            Prompt::Extension => "extension: ",
+            Prompt::Search => "search: ",
+            Prompt::PreviewSearch => "find: ",
+        }
+    }
+}
+
+// Function to get the rows to list: the matches passing `filters`, in `sort` order, nested
+// by directory when the tree view is on (`tree` holds its collapsed directories). Only
+// indices are reordered so they keep pointing into `rg_matches`. The query is matched
+// against `listed_paths`, the paths as listed.
+fn list_rows(
+    rg_matches: &[RgMatch],
+    filters: &Filters,
+    listed_paths: &[String],
+    exact: bool,
+    sort: SortMode,
+    reverse: bool,
+    tree: Option<&HashSet<String>>,
+) -> Vec<ListRow> {
+    let data = |idx: usize| rg_matches[idx].data.as_ref();
+    let mut indices = filter_matches(rg_matches, &filters.query, listed_paths, exact);
+    indices.retain(|&idx| {
+        !filters.hidden.contains(&idx)
+            && data(idx).is_some_and(|d| filters.has_extension(&d.path.text))
+    });
+    match sort {
+        SortMode::Input => {}
+        SortMode::Path => {
+            indices.sort_by_key(|&idx| data(idx).map(|d| (&d.path.text, d.line_number)))
+        }
+        SortMode::LineNumber => indices.sort_by_key(|&idx| data(idx).map(|d| d.line_number)),
+    }
+    if reverse {
+        indices.reverse();
+    }
+    match tree {
+        Some(collapsed) => tree_rows(rg_matches, &indices, collapsed),
+        None => flat_rows(indices),
+    }
+}
+
+// Function to get the indices of the matches to list for `query`. By default paths are
+// fuzzy matched and sorted best match first; with `exact` the path or matched text has to
+// contain the query, case-insensitive unless the query has uppercase characters. Paths are
+// matched as listed in `listed_paths`, so the highlighted characters are the matched ones.
+fn filter_matches(
+    rg_matches: &[RgMatch],
+    query: &str,
+    listed_paths: &[String],
+    exact: bool,
+) -> Vec<usize> {
+    if query.is_empty() {
+        return (0..rg_matches.len()).collect();
+    }
+
+    if !exact {
+        let mut scored: Vec<(i64, usize)> = rg_matches
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, m)| {
+                m.data.as_ref()?;
+                let (score, _) = fuzzy_match(query, &listed_paths[idx])?;
+                Some((score, idx))
+            })
+            .collect();
+        // Stable sort keeps the original order among equal scores
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        return scored.into_iter().map(|(_, idx)| idx).collect();
+    }
+
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let query = if ignore_case {
+        query.to_lowercase()
+    } else {
+        query.to_string()
+    };
+    let contains = |haystack: &str| {
+        if ignore_case {
+            haystack.to_lowercase().contains(&query)
+        } else {
+            haystack.contains(&query)
+        }
+    };
+
+    rg_matches
+        .iter()
+        .enumerate()
+        .filter(|(idx, m)| {
+            m.data
+                .as_ref()
+                .is_some_and(|data| contains(&listed_paths[*idx]) || contains(&data.lines.text))
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+// Function to style the characters at `positions` (char indices into `text`) as matched
+fn highlight_chars(text: String, positions: &[usize]) -> Line<'static> {
+    if positions.is_empty() {
+        return Line::from(text);
+    }
+
+    let mut spans = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (idx, c) in text.chars().enumerate() {
+        let matched = positions.contains(&idx);
+        if matched != run_matched && !run.is_empty() {
+            spans.push(fuzzy_span(std::mem::take(&mut run), run_matched));
+        }
+        run_matched = matched;
+        run.push(c);
+    }
+    spans.push(fuzzy_span(run, run_matched));
+    Line::from(spans)
+}
+
+fn fuzzy_span(content: String, matched: bool) -> Span<'static> {
+    if matched {
+        Span::styled(content, FUZZY_MATCH_STYLE)
+    } else {
+        Span::raw(content)
+    }
+}
+
+// Function to measure the widest line number and `:col` among the given rows, so their
+// position column is the same width on each of them
+fn position_widths(rg_matches: &[RgMatch], rows: &[ListRow]) -> (usize, usize) {
+    rows.iter()
+        .filter_map(|row| rg_matches[row.match_idx()?].data.as_ref())
+        .fold((0, 0), |(line_width, column_width), data| {
+            let column = data
+                .column()
+                .map_or(0, |column| column.to_string().len() + 1);
+            (
+                line_width.max(data.line_number.to_string().len()),
+                column_width.max(column),
+            )
+        })
+}
+
+// Function to format a match's `line:col` for the list, the line number right-aligned and
+// the column padded after it, followed by a space
+fn position_column(data: &MatchData, (line_width, column_width): (usize, usize)) -> String {
+    let column = data
+        .column()
+        .map(|column| format!(":{}", column))
+        .unwrap_or_default();
+    format!(
+        "{:>line_width$}{:<column_width$} ",
+        data.line_number,
+        column,
+        line_width = line_width,
+        column_width = column_width
+    )
+}
+
+// Function to build the text shown for a match in the result list, along with which path
+// characters it shows (see `display_path`). `file_count` is which match in its file this is
+// and how many the file has: grouped entries show the total, others `[3/7]` when the file
+// has more than one.
+fn list_item_label(
+    data: &MatchData,
+    path: &str,
+    file_count: (usize, usize),
+    grouped: bool,
+    paths_only: bool,
+    basename: bool,
+    max_width: usize,
+) -> (String, Vec<Option<usize>>) {
+    let (ordinal, total) = file_count;
+    let count = if total > 1 {
+        format!(" [{}/{}]", ordinal, total)
+    } else {
+        String::new()
+    };
+    // Keep the counts visible after a shortened path; the matched text is what gets cut off
+    let (suffix, reserved) = if grouped {
+        let suffix = format!(" ({})", total);
+        let reserved = suffix.len();
+        (suffix, reserved)
+    } else if paths_only {
+        let reserved = count.len();
+        (count, reserved)
+    } else {
+        // The line and column have a column of their own in front, see `position_column`
+        let reserved = count.len();
+        // A multiline match is listed by its first line
+        let text = data.lines.text.lines().next().unwrap_or_default();
+        (format!("{} {}", count, text.trim()), reserved)
+    };
+
+    // Along with a column for the `…` of cut off matched text
+    let reserved_width = suffix[..reserved].width() + usize::from(suffix.len() > reserved);
+    let path_width = max_width.saturating_sub(reserved_width);
+    let (mut label, mut shown) = display_path(path, basename, path_width);
+    // Even the file name alone can be too wide
+    if label.width() > path_width {
+        let (cut, kept) = fit_to_width(&label, path_width);
+        shown.truncate(kept);
+        // No room at all leaves out the ellipsis too
+        if cut.chars().count() > kept {
+            shown.push(None);
+        }
+        label = cut;
+    }
+    label.push_str(&suffix);
+    if label.width() > max_width {
+        label = fit_to_width(&label, max_width).0;
+    }
+    (label, shown)
+}
+
+// Function to cut `text` to at most `max_width` display columns, ending it with `…` when
+// anything had to go. Widths come from the characters, so wide (CJK, emoji) ones count
+// double, and one that would straddle the edge goes whole. Also returns how many characters
+// of `text` were kept.
+fn fit_to_width(text: &str, max_width: usize) -> (String, usize) {
+    if text.width() <= max_width {
+        return (text.to_string(), text.chars().count());
+    }
+    if max_width == 0 {
+        return (String::new(), 0);
+    }
+
+    // Leave a column for the ellipsis
+    let room = max_width - 1;
+    let mut fitted = String::new();
+    let mut width = 0;
+    let mut kept = 0;
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if width + char_width > room {
+            break;
+        }
+        width += char_width;
+        fitted.push(c);
+        kept += 1;
+    }
+    fitted.push('…');
+    (fitted, kept)
+}
+
+// Function to style the paths of the matches added to `rg_matches` since `listed_paths` was
+// last brought up to date. Matches of a file arrive together, so a path is only resolved
+// again when it differs from the one before it.
+fn style_new_paths(
+    listed_paths: &mut Vec<String>,
+    rg_matches: &[RgMatch],
+    style: PathStyle,
+    cwd: Option<&Path>,
+) {
+    for idx in listed_paths.len()..rg_matches.len() {
+        let path = rg_matches[idx]
+            .data
+            .as_ref()
+            .map_or("", |d| d.path.text.as_str());
+        let previous = idx
+            .checked_sub(1)
+            .and_then(|prev| rg_matches[prev].data.as_ref())
+            .filter(|d| d.path.text == path);
+        let listed = match previous {
+            Some(_) => listed_paths[idx - 1].clone(),
+            None => styled_path(path, style, cwd).into_owned(),
+        };
+        listed_paths.push(listed);
+    }
+}
+
+// Function to get the path in the given style, for display only. Relative paths are already
+// relative to the current directory; absolute ones outside of it stay absolute. Files that
+// can't be resolved, e.g. because they were deleted, are shown as given.
+fn styled_path<'a>(path: &'a str, style: PathStyle, cwd: Option<&Path>) -> Cow<'a, str> {
+    let as_path = Path::new(path);
+    match style {
+        PathStyle::AsGiven => Cow::Borrowed(path),
+        PathStyle::Relative if as_path.is_relative() => {
+            Cow::Borrowed(path.strip_prefix("./").unwrap_or(path))
+        }
+        PathStyle::Relative => {
+            let resolved = fs::canonicalize(as_path).unwrap_or_else(|_| as_path.to_path_buf());
+            match cwd.and_then(|cwd| resolved.strip_prefix(cwd).ok()) {
+                Some(relative) => Cow::Owned(relative.to_string_lossy().into_owned()),
+                None => Cow::Borrowed(path),
+            }
+        }
+        PathStyle::Absolute => match fs::canonicalize(as_path) {
+            Ok(resolved) => Cow::Owned(resolved.to_string_lossy().into_owned()),
+            Err(_) => Cow::Borrowed(path),
+        },
+    }
+}
+
+// Function to get the path as listed: just the file name with `basename`, otherwise the full
+// path with directories in the middle replaced by `…` until it fits in `max_width` columns,
+// e.g. `src/…/main.rs`. Also returns, for each displayed character, the index of the path
+// character it shows (`None` for the ellipsis).
+fn display_path(path: &str, basename: bool, max_width: usize) -> (String, Vec<Option<usize>>) {
+    let chars: Vec<char> = path.chars().collect();
+    let separators: Vec<usize> = (0..chars.len())
+        .filter(|&i| matches!(chars[i], '/' | '\\'))
+        .collect();
+    let shown = |range: std::ops::Range<usize>| -> (String, Vec<Option<usize>>) {
+        (
+            chars[range.clone()].iter().collect(),
+            range.map(Some).collect(),
+        )
+    };
+
+    if basename {
+        let start = separators.last().map_or(0, |&i| i + 1);
+        return shown(start..chars.len());
+    }
+    // Keep the first directory (past the root of an absolute path), then as many trailing
+    // components as fit after the ellipsis
+    let first = usize::from(separators.first() == Some(&0));
+    if path.width() <= max_width || separators.len() < first + 2 {
+        return shown(0..chars.len());
+    }
+
+    let head_end = separators[first] + 1;
+    let room = max_width.saturating_sub(chars[..head_end].iter().collect::<String>().width() + 1);
+    let tail_start = separators[first + 1..]
+        .iter()
+        .copied()
+        .find(|&i| chars[i..].iter().collect::<String>().width() <= room)
+        .unwrap_or(separators[separators.len() - 1]);
+
+    let (mut label, mut map) = shown(0..head_end);
+    label.push('…');
+    map.push(None);
+    let (tail, tail_map) = shown(tail_start..chars.len());
+    label.push_str(&tail);
+    map.extend(tail_map);
+    (label, map)
+}
+
+// Frames of the loading spinner in the status bar, advanced every `SPINNER_INTERVAL`
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+// How long the `--search` query has to stay unchanged before rg is rerun
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(150);
+
+// How long the selection has to stay put before its preview is rendered
+const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(80);
+
+// Rows the preview scrolls per mouse wheel tick
+const MOUSE_SCROLL_LINES: u16 = 3;
+
+// Columns the preview scrolls sideways per `h`/`l`
+const HORIZONTAL_SCROLL_STEP: u16 = 8;
+
+// Step and bounds, in percent, for resizing the list pane with `<`/`>`
+const SPLIT_STEP: u16 = 5;
+const MIN_LIST_PERCENT: u16 = 10;
+const MAX_LIST_PERCENT: u16 = 90;
+
+// Style of the preview pane when the preview couldn't be rendered
+const PREVIEW_ERROR_STYLE: Style = Style::new().fg(Color::Red);
+
+// Function to describe why a preview failed, with the full chain of causes (which includes
+// the stderr of a failed `bat` or preview command) on separate lines
+fn preview_error_text(error: &anyhow::Error) -> Text<'static> {
+    let mut lines = vec![Line::styled(
+        "Failed to render the preview",
+        PREVIEW_ERROR_STYLE.add_modifier(Modifier::BOLD),
+    )];
+    for cause in error.chain() {
+        lines.extend(
+            cause
+                .to_string()
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| Line::styled(line.to_string(), PREVIEW_ERROR_STYLE)),
+        );
+    }
+    Text::from(lines)
+}
+
+// Indentation per level of the tree view, and the style of its directory headings
+const TREE_INDENT: &str = "  ";
+const TREE_DIR_STYLE: Style = Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+
+// Function to create a bordered pane in the theme's border color
+fn bordered(theme: &Theme) -> Block<'static> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.border)
+}
+
+// Function to get the first visible list row: the previous offset, moved only as far as
+// needed to keep `selected` among the `page_size` visible rows
+fn visible_offset(offset: usize, selected: usize, page_size: usize, len: usize) -> usize {
+    let offset = if selected < offset {
+        selected
+    } else if selected >= offset + page_size {
+        selected + 1 - page_size
+    } else {
+        offset
+    };
+    offset.min(len.saturating_sub(page_size))
+}
+
+// Function to show `message` centered inside a bordered pane that has nothing to list
+fn render_empty_state(f: &mut Frame, area: Rect, message: &str) {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    if inner.height == 0 {
+        return;
+    }
+    let row = Rect {
+        y: inner.y + inner.height / 2,
+        height: 1,
+        ..inner
+    };
+    let text = Paragraph::new(message)
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::DarkGray));
+    f.render_widget(text, row);
+}
+
+// How far reading the matches has got, for the status bar
+struct LoadProgress {
+    // Current spinner frame while matches are still being read
+    spinner: Option<char>,
+    // The `--max-matches` limit, once the input went past it
+    truncated_at: Option<usize>,
+}
+
+// Function to build the status bar text: position in the results, whether matches are still
+// loading or were cut off, the selected location, the active filters, ripgrep's totals and
+// any transient message
+fn status_text(
+    selected_idx: usize,
+    rg_matches: &[RgMatch],
+    filtered: &[ListRow],
+    filters: &Filters,
+    progress: LoadProgress,
+    stats: Option<&Stats>,
+    message: Option<&str>,
+) -> String {
+    let mut text = if filtered.is_empty() {
+        " 0/0".to_string()
+    } else {
+        format!(" {}/{}", selected_idx + 1, filtered.len())
+    };
+    if let Some(frame) = progress.spinner {
+        text.push_str(&format!(" {} loading, {} so far", frame, rg_matches.len()));
+    }
+    if let Some(max) = progress.truncated_at {
+        text.push_str(&format!(" (truncated, showing first {})", max));
+    }
+
+    if let Some(data) = selected_data(rg_matches, filtered, selected_idx) {
+        text.push_str(&format!("  {}:{}", data.path.text, data.location()));
+    }
+    if !filters.search.is_empty() {
+        text.push_str(&format!("  [search: {}]", filters.search));
+    }
+    if !filters.query.is_empty() {
+        text.push_str(&format!("  [filter: {}]", filters.query));
+    }
+    if !filters.extensions.is_empty() {
+        text.push_str(&format!("  [extension: {}]", filters.extensions));
+    }
+    if let Some(stats) = stats {
+        text.push_str("  (");
+        text.push_str(&stats.describe());
+        text.push(')');
+    }
+    if let Some(message) = message {
+        text.push_str("  | ");
+        text.push_str(message);
+    }
+    text
+}
+
+// Function to draw the keybinding help as a centered popup over the rest of the UI
+fn render_help(f: &mut Frame, entries: &[(String, &str)], theme: &Theme) {
+    let keys_width = entries
+        .iter()
+        .map(|(keys, _)| keys.len())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Line> = entries
+        .iter()
+        .map(|(keys, description)| {
+            Line::from(vec![
+                Span::styled(
+                    format!(" {:<width$}  ", keys, width = keys_width),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(*description),
+            ])
+        })
+        .collect();
+
+    let area = f.area();
+    let width = (lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 3).min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines).block(bordered(theme).title("Help")),
+        popup,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn match_in(path: &str, text: &str) -> MatchData {
+        serde_json::from_value(serde_json::json!({
+            "path": { "text": path },
+            "lines": { "text": text },
+            "line_number": 7,
+            "absolute_offset": 1234,
+            "submatches": [],
+        }))
+        .unwrap()
+    }
+
+    // Function to check that every displayed path character is the one `shown` says it is
+    fn assert_shows_path(label: &str, shown: &[Option<usize>], path: &str) {
+        let path: Vec<char> = path.chars().collect();
+        for (c, index) in label.chars().zip(shown) {
+            match index {
+                Some(i) => assert_eq!(c, path[*i], "in {}", label),
+                None => assert_eq!(c, '…', "in {}", label),
+            }
+        }
+    }
+
+    #[test]
+    fn wide_characters_are_never_cut_in_half() {
+        assert_eq!(fit_to_width("漢字漢字漢字", 7), ("漢字漢…".to_string(), 3));
+        // The fourth character would straddle the edge, so it goes whole
+        assert_eq!(fit_to_width("漢字漢字漢字", 8), ("漢字漢…".to_string(), 3));
+        assert_eq!(fit_to_width("😀😀😀", 6), ("😀😀😀".to_string(), 3));
+        assert_eq!(fit_to_width("😀😀😀", 5), ("😀😀…".to_string(), 2));
+    }
+
+    #[test]
+    fn wide_paths_are_shortened_in_the_middle() {
+        let path = "src/漢字/モジュール/ファイル.rs";
+        assert_eq!(path.width(), 31);
+        let (label, shown) = display_path(path, false, 20);
+        assert_eq!(label, "src/…/ファイル.rs");
+        assert_eq!(label.width(), 17);
+        assert_shows_path(&label, &shown, path);
+    }
+
+    #[test]
+    fn wide_list_labels_fit_their_width() {
+        let data = match_in("src/漢字/モジュール/ファイル.rs", "let 値 = 1;");
+        for max_width in 1..40 {
+            for (paths_only, grouped) in [(true, false), (false, false), (false, true)] {
+                let (label, shown) = list_item_label(
+                    &data,
+                    &data.path.text,
+                    (1, 1),
+                    grouped,
+                    paths_only,
+                    false,
+                    max_width,
+                );
+                assert!(label.width() <= max_width, "{} in {}", label, max_width);
+                assert_shows_path(&label, &shown, &data.path.text);
+            }
+        }
+
+        let (label, _) = list_item_label(&data, &data.path.text, (1, 1), false, true, false, 31);
+        assert_eq!(label, data.path.text);
+        let (label, shown) =
+            list_item_label(&data, &data.path.text, (1, 1), false, true, false, 10);
+        assert_eq!(label, "src/…/フ…");
+        assert_eq!(shown.len(), label.chars().count());
+    }
+
+    #[test]
+    fn json_selection_keeps_the_absolute_offset() {
+        let line = r#"{"type":"match","data":{"path":{"text":"src/lib.rs"},"lines":{"text":"pub mod app;\n"},"line_number":3,"absolute_offset":57,"submatches":[{"match":{"text":"app"},"start":8,"end":11}]}}"#;
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        let data: MatchData = serde_json::from_value(record["data"].clone()).unwrap();
+        let printed: serde_json::Value =
+            serde_json::from_str(&format_selection(&data, OutputFormat::Json)).unwrap();
+        assert_eq!(
+            printed,
+            serde_json::json!({ "path": "src/lib.rs", "line_number": 3, "absolute_offset": 57 })
+        );
+
+        // Plain input has no offset to report
+        let mut data = data;
+        data.absolute_offset = None;
+        let printed: serde_json::Value =
+            serde_json::from_str(&format_selection(&data, OutputFormat::Json)).unwrap();
+        assert!(printed.get("absolute_offset").is_none());
+    }
+
+    #[test]
+    fn action_placeholders_become_quoted_parameters() {
+        assert_eq!(
+            action_script("code -g {file}:{line}"),
+            r#"code -g "$1":"$2""#
+        );
+        assert_eq!(action_script("less +{line} '{file}'"), r#"less +"$2" "$1""#);
+        assert_eq!(
+            action_script(r#"echo "{file}" '{line}'"#),
+            r#"echo "$1" "$2""#
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Function to encode bytes as standard padded base64
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// Function to decode standard base64, with or without padding
+pub fn decode(text: &str) -> Result<Vec<u8>> {
+    let digits = text.trim_end_matches('=').as_bytes();
+    let mut decoded = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        if chunk.len() == 1 {
+            return Err(anyhow::anyhow!("Truncated base64 `{}`", text));
+        }
+        let mut n = 0u32;
+        for (i, &digit) in chunk.iter().enumerate() {
+            let value = ALPHABET
+                .iter()
+                .position(|&c| c == digit)
+                .ok_or_else(|| anyhow::anyhow!("Invalid base64 `{}`", text))?;
+            n |= (value as u32) << (18 - 6 * i);
+        }
+        // Every digit past the first carries one more byte
+        for i in 0..chunk.len() - 1 {
+            decoded.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_with_padding() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg==");
+        assert_eq!(encode(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+
+    #[test]
+    fn decodes_with_or_without_padding() {
+        assert_eq!(decode("Zm9vYg==").unwrap(), b"foob");
+        assert_eq!(decode("Zm9vYg").unwrap(), b"foob");
+        assert_eq!(decode("Zm9vYmE=").unwrap(), b"fooba");
+        // A path that isn't valid UTF-8, as ripgrep reports it
+        assert_eq!(decode("Y2Fm6S50eHQ=").unwrap(), b"caf\xe9.txt");
+    }
+
+    #[test]
+    fn round_trips_every_byte() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(decode("Zm9v!").is_err());
+        assert!(decode("Zm9vY").is_err());
+        assert!(decode("Zm 9v").is_err());
+    }
+}
@@ -0,0 +1,590 @@
+use crate::clipboard::ClipboardBackend;
+use crate::config::Config;
+use crate::preview::{Highlighter, PreviewCommand};
+use anyhow::{Context, Result};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Default number of lines shown before and after the match in the preview
+pub const DEFAULT_CONTEXT: usize = 15;
+
+// Default size in MB above which files aren't previewed
+pub const DEFAULT_MAX_PREVIEW_SIZE: u64 = 10;
+
+// Default terminal width in columns below which `--layout auto` stacks the panes
+pub const DEFAULT_VERTICAL_BELOW: u16 = 100;
+
+// Default longest time in milliseconds the UI sleeps without input or new matches
+pub const DEFAULT_POLL_INTERVAL: u64 = 1000;
+
+// How paths are shown in the list. Opening and printing always use the path as reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    // Exactly as ripgrep reported them
+    AsGiven,
+    // Relative to the current directory where possible
+    Relative,
+    // Canonicalized absolute paths
+    Absolute,
+}
+
+impl PathStyle {
+    // Function to get the style `a` switches to next
+    pub fn next(self) -> Self {
+        match self {
+            PathStyle::AsGiven => PathStyle::Relative,
+            PathStyle::Relative => PathStyle::Absolute,
+            PathStyle::Absolute => PathStyle::AsGiven,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PathStyle::AsGiven => "as given",
+            PathStyle::Relative => "relative",
+            PathStyle::Absolute => "absolute",
+        }
+    }
+}
+
+impl FromStr for PathStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "given" => Ok(Self::AsGiven),
+            "relative" => Ok(Self::Relative),
+            "absolute" => Ok(Self::Absolute),
+            _ => Err(anyhow::anyhow!(
+                "Unknown path style `{}`, expected `given`, `relative` or `absolute`",
+                s
+            )),
+        }
+    }
+}
+
+// How the list and preview panes are arranged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaneLayout {
+    // Side by side, or stacked when the terminal is narrower than `vertical_below`
+    Auto,
+    // List on the left, preview on the right
+    Horizontal,
+    // List on top, preview below
+    Vertical,
+}
+
+impl FromStr for PaneLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "horizontal" => Ok(Self::Horizontal),
+            "vertical" => Ok(Self::Vertical),
+            _ => Err(anyhow::anyhow!(
+                "Unknown layout `{}`, expected `auto`, `horizontal` or `vertical`",
+                s
+            )),
+        }
+    }
+}
+
+// Where Enter opens the editor when rgnav runs inside tmux
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TmuxPane {
+    // In rgnav's own pane, suspending rgnav meanwhile
+    Off,
+    // In a new pane split off rgnav's
+    Split,
+    // In a new tmux window
+    Window,
+}
+
+impl FromStr for TmuxPane {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(Self::Off),
+            "split" => Ok(Self::Split),
+            "window" => Ok(Self::Window),
+            _ => Err(anyhow::anyhow!(
+                "Unknown tmux mode `{}`, expected `off`, `split` or `window`",
+                s
+            )),
+        }
+    }
+}
+
+// How the confirmed selection is printed on exit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    // Just the file path
+    Path,
+    // `path:line`
+    PathLine,
+    // `{"path": ..., "line_number": ...}`, one object per line
+    Json,
+    // `path:line:column:text`, like `rg --vimgrep`
+    Vimgrep,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "path" => Ok(Self::Path),
+            "path:line" => Ok(Self::PathLine),
+            "json" => Ok(Self::Json),
+            "vimgrep" => Ok(Self::Vimgrep),
+            _ => Err(anyhow::anyhow!(
+                "Unknown output format `{}`, expected `path`, `path:line`, `json` or `vimgrep`",
+                s
+            )),
+        }
+    }
+}
+
+// Command-line options
+#[derive(Debug)]
+pub struct Args {
+    pub context: usize,
+    pub highlighter: Highlighter,
+    // Theme passed to `bat --theme`, otherwise bat's own default
+    pub bat_theme: Option<String>,
+    // Command rendering previews instead of `bat`
+    pub preview_command: Option<PreviewCommand>,
+    // Size in MB above which files get a placeholder instead of a preview
+    pub max_preview_size: u64,
+    // Start with line numbers shown in the preview
+    pub line_numbers: bool,
+    // Mark how many lines of the file are left out above and below the preview
+    pub fold_markers: bool,
+    // Preview files changed since HEAD as a `git diff` around the match
+    pub git_context: bool,
+    // Preview and open the file a symlinked path points at
+    pub follow_symlinks: bool,
+    pub path_style: PathStyle,
+    pub layout: PaneLayout,
+    // Terminal width below which the automatic layout stacks the panes
+    pub vertical_below: u16,
+    // Show each file only once, previewing its first match
+    pub unique: bool,
+    // Show each file once along with its number of matches
+    pub group: bool,
+    // List only file paths, without the line number and matched text
+    pub paths_only: bool,
+    // Saved `rg --json` output to read instead of stdin
+    pub input: Option<String>,
+    // Directory to search by running `rg` for a query typed in rgnav, instead of reading
+    // ripgrep output
+    pub search: Option<String>,
+    // Shell command producing the matches, rerun by `R`. Also read at startup when nothing
+    // is piped in.
+    pub rg_cmd: Option<String>,
+    // Shell command Enter runs on the selected match instead of opening `$EDITOR`, with
+    // `{file}` and `{line}` placeholders
+    pub action_cmd: Option<String>,
+    // Open the editor in a new tmux pane or window, keeping rgnav running, when inside tmux
+    pub tmux: TmuxPane,
+    // File `Q` writes the listed matches to as a quickfix list; without it they're printed on
+    // exit
+    pub quickfix_out: Option<String>,
+    pub clipboard: ClipboardBackend,
+    pub output_format: OutputFormat,
+    // Render without colors, also turned on by a non-empty `NO_COLOR`
+    pub no_color: bool,
+    // Draw the UI inline below the prompt instead of on the alternate screen
+    pub no_alt_screen: bool,
+    // Filter by substring instead of fuzzy matching
+    pub exact: bool,
+    // Moving past either end of the list continues from the other end
+    pub wrap_around: bool,
+    // Separator between path, line number and text in plain (non-JSON) input
+    pub field_separator: Option<String>,
+    // Number of matches read at most, the rest of the input is skipped
+    pub max_matches: Option<usize>,
+    // Print what was parsed from the input instead of starting the UI
+    pub debug_parse: bool,
+    // Longest the UI sleeps between checks when nothing wakes it
+    pub poll_interval: Duration,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            context: DEFAULT_CONTEXT,
+            highlighter: Highlighter::Bat,
+            bat_theme: None,
+            preview_command: None,
+            max_preview_size: DEFAULT_MAX_PREVIEW_SIZE,
+            line_numbers: false,
+            fold_markers: false,
+            git_context: false,
+            follow_symlinks: false,
+            path_style: PathStyle::AsGiven,
+            layout: PaneLayout::Auto,
+            vertical_below: DEFAULT_VERTICAL_BELOW,
+            unique: false,
+            group: false,
+            paths_only: false,
+            input: None,
+            search: None,
+            rg_cmd: None,
+            action_cmd: None,
+            tmux: TmuxPane::Off,
+            quickfix_out: None,
+            clipboard: ClipboardBackend::Auto,
+            output_format: OutputFormat::PathLine,
+            no_color: false,
+            no_alt_screen: false,
+            exact: false,
+            wrap_around: false,
+            field_separator: None,
+            max_matches: None,
+            debug_parse: false,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL),
+        }
+    }
+}
+
+impl Args {
+    // Function to build the defaults for this run, with config file values layered on top
+    // of the built-in ones
+    fn from_config(config: &Config) -> Result<Self> {
+        let mut args = Self::default();
+        if let Some(context) = config.get_usize("", "context")? {
+            args.context = context;
+        }
+        if let Some(highlighter) = config.get_str("", "highlighter")? {
+            args.highlighter = highlighter.parse()?;
+        }
+        if let Some(theme) = config.get_str("", "bat_theme")? {
+            args.bat_theme = Some(bat_theme(theme)?);
+        }
+        if let Some(command) = config.get_str("", "preview_command")? {
+            args.preview_command = Some(command.parse()?);
+        }
+        if let Some(size) = config.get_usize("", "max_preview_size")? {
+            args.max_preview_size = size as u64;
+        }
+        if let Some(line_numbers) = config.get_bool("", "line_numbers")? {
+            args.line_numbers = line_numbers;
+        }
+        if let Some(fold_markers) = config.get_bool("", "fold_markers")? {
+            args.fold_markers = fold_markers;
+        }
+        if let Some(git_context) = config.get_bool("", "git_context")? {
+            args.git_context = git_context;
+        }
+        if let Some(follow_symlinks) = config.get_bool("", "follow_symlinks")? {
+            args.follow_symlinks = follow_symlinks;
+        }
+        if let Some(style) = config.get_str("", "path_style")? {
+            args.path_style = style.parse()?;
+        }
+        if let Some(layout) = config.get_str("", "layout")? {
+            args.layout = layout.parse()?;
+        }
+        if let Some(width) = config.get_usize("", "vertical_below")? {
+            args.vertical_below = width.min(u16::MAX as usize) as u16;
+        }
+        if let Some(unique) = config.get_bool("", "unique")? {
+            args.unique = unique;
+        }
+        if let Some(wrap_around) = config.get_bool("", "wrap")? {
+            args.wrap_around = wrap_around;
+        }
+        if let Some(separator) = config.get_str("", "field_separator")? {
+            args.field_separator = Some(field_separator(separator)?);
+        }
+        if let Some(max) = config.get_usize("", "max_matches")? {
+            args.max_matches = Some(max);
+        }
+        if let Some(interval) = config.get_usize("", "poll_interval")? {
+            args.poll_interval = poll_interval(interval as u64)?;
+        }
+        if let Some(clipboard) = config.get_str("", "clipboard")? {
+            args.clipboard = clipboard.parse()?;
+        }
+        if let Some(command) = config.get_str("", "action_cmd")? {
+            args.action_cmd = Some(command.to_string());
+        }
+        if let Some(tmux) = config.get_str("", "tmux")? {
+            args.tmux = tmux.parse()?;
+        }
+        if let Some(format) = config.get_str("", "output_format")? {
+            args.output_format = format.parse()?;
+        }
+        if let Some(no_color) = config.get_bool("", "no_color")? {
+            args.no_color = no_color;
+        }
+        if let Some(no_alt_screen) = config.get_bool("", "no_alt_screen")? {
+            args.no_alt_screen = no_alt_screen;
+        }
+        // https://no-color.org: set and not empty
+        if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            args.no_color = true;
+        }
+        Ok(args)
+    }
+}
+
+// Function to check a `--field-separator` value, which can't be empty
+fn field_separator(separator: &str) -> Result<String> {
+    if separator.is_empty() {
+        return Err(anyhow::anyhow!("`--field-separator` can't be empty"));
+    }
+    Ok(separator.to_string())
+}
+
+// Function to check a `--poll-interval` value. Without any sleep the UI would spin a core
+// while waiting.
+fn poll_interval(millis: u64) -> Result<Duration> {
+    if millis == 0 {
+        return Err(anyhow::anyhow!("`--poll-interval` must be at least 1ms"));
+    }
+    Ok(Duration::from_millis(millis))
+}
+
+// Function to check a `--bat-theme` value, which has to name a theme
+fn bat_theme(name: &str) -> Result<String> {
+    if name.trim().is_empty() {
+        return Err(anyhow::anyhow!("`--bat-theme` expects a theme name"));
+    }
+    Ok(name.to_string())
+}
+
+// Usage text printed by `--help`
+const HELP: &str = "\
+Browse ripgrep results with a live preview
+
+Usage: rg --json PATTERN | rgnav [OPTIONS]
+       rgnav [OPTIONS] FILE
+       rgnav --search DIR [OPTIONS]
+       rgnav --rg-cmd CMD [OPTIONS]
+
+Arguments:
+  FILE                      Saved `rg --json` (or `path:line:text`) output to read instead of stdin
+
+Options:
+  -C, --context N           Lines of context around the match in the preview [default: 15]
+      --highlighter NAME    How previews are highlighted: `bat` or `none` [default: bat]
+      --bat-theme NAME      Theme bat highlights previews with
+      --list-bat-themes     Print the themes bat knows and exit
+      --preview-command CMD Command rendering previews instead of bat, with {file}, {start},
+                            {end} and {line} placeholders
+      --max-preview-size MB Show a placeholder for files larger than this [default: 10]
+      --line-numbers        Show line numbers in the preview (toggle with #)
+      --fold-markers        Show how many lines the preview leaves out above and below
+      --git-context         Preview files changed since HEAD with git's added and removed
+                            lines
+      --follow-symlinks     Preview and open the file a symlink points at, listing the link
+      --path-style STYLE    Show paths `given`, `relative` or `absolute` [default: given]
+      --layout LAYOUT       Arrange the panes `horizontal`, `vertical` or `auto` [default: auto]
+      --vertical-below COLS Width below which `auto` stacks the list above the preview
+                            [default: 100]
+      --unique              Show each file only once
+      --group               Show each file once with its number of matches
+      --paths-only          List only file paths
+      --exact               Filter by substring instead of fuzzy matching
+      --wrap                Wrap around when moving past either end of the list
+      --search DIR          Run rg in DIR for a query typed in rgnav
+      --rg-cmd CMD          Shell command producing the matches, rerun with R
+      --action-cmd CMD      Shell command Enter runs instead of opening $EDITOR, with {file}
+                            and {line} placeholders; they are quoted already, so don't
+                            quote them inside a longer string
+      --tmux MODE           Inside tmux, open the editor in a new `split` or `window` and
+                            keep rgnav running [default: off]
+      --quickfix-out PATH   File `Q` writes the listed matches to as a quickfix list
+      --clipboard BACKEND   Where copies go: `auto`, `native` or `osc52` [default: auto]
+      --output-format FMT   How a confirmed selection is printed: `path`, or with the line
+                            number `path:line`, `json` or `vimgrep` [default: path:line]
+      --no-color            Don't use colors (also when NO_COLOR is set)
+      --no-alt-screen       Draw the UI inline below the prompt, 20 rows high, instead of
+                            on the alternate screen
+      --field-separator SEP Separator of path, line and text in plain input [default: :]
+      --max-matches N       Read only the first N matches and skip the rest of the input
+      --debug-parse         Print the parsed matches instead of starting the UI
+      --poll-interval MS    Longest the UI sleeps when neither input nor new matches wake
+                            it [default: 1000]
+  -h, --help                Print this help
+  -V, --version             Print the version
+
+Press ? inside rgnav for the key bindings.";
+
+// Function to handle `--help`, `--version` and `--list-bat-themes`, which print to stdout and
+// exit right away, before the config is loaded or the terminal and stdin are touched
+pub fn print_info_if_requested() {
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", HELP);
+                std::process::exit(0);
+            }
+            "-V" | "--version" => {
+                println!("rgnav {}", env!("CARGO_PKG_VERSION"));
+                std::process::exit(0);
+            }
+            "--list-bat-themes" => match Command::new("bat").arg("--list-themes").status() {
+                Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+                Err(e) => {
+                    eprintln!("Error: Failed to run `bat --list-themes`: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+// Function to parse the command-line arguments passed to `rgnav`. Flags override the
+// values from `config`.
+pub fn parse_args(config: &Config) -> Result<Args> {
+    parse_args_from(config, std::env::args().skip(1))
+}
+
+fn parse_args_from(config: &Config, args: impl IntoIterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args::from_config(config)?;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        // Whether the flag took the `=value`, which switches can't
+        let mut took_inline = false;
+        let mut value = |name: &str| {
+            if let Some(value) = &inline_value {
+                took_inline = true;
+                return Ok(value.clone());
+            }
+            args.next()
+                .with_context(|| format!("Missing value for `{}`", name))
+        };
+
+        match flag.as_str() {
+            "--context" | "-C" => {
+                let raw = value("--context")?;
+                parsed.context = raw.parse().with_context(|| {
+                    format!("`--context` expects a non-negative integer, got `{}`", raw)
+                })?;
+            }
+            "--highlighter" => {
+                parsed.highlighter = value("--highlighter")?.parse()?;
+            }
+            "--bat-theme" => parsed.bat_theme = Some(bat_theme(&value("--bat-theme")?)?),
+            "--preview-command" => {
+                parsed.preview_command = Some(value("--preview-command")?.parse()?);
+            }
+            "--max-preview-size" => {
+                let raw = value("--max-preview-size")?;
+                parsed.max_preview_size = raw.parse().with_context(|| {
+                    format!("`--max-preview-size` expects a size in MB, got `{}`", raw)
+                })?;
+            }
+            "--line-numbers" => parsed.line_numbers = true,
+            "--fold-markers" => parsed.fold_markers = true,
+            "--git-context" => parsed.git_context = true,
+            "--follow-symlinks" => parsed.follow_symlinks = true,
+            "--path-style" => parsed.path_style = value("--path-style")?.parse()?,
+            "--layout" => parsed.layout = value("--layout")?.parse()?,
+            "--vertical-below" => {
+                let raw = value("--vertical-below")?;
+                parsed.vertical_below = raw.parse().with_context(|| {
+                    format!(
+                        "`--vertical-below` expects a number of columns, got `{}`",
+                        raw
+                    )
+                })?;
+            }
+            "--unique" => parsed.unique = true,
+            "--group" => parsed.group = true,
+            "--paths-only" => parsed.paths_only = true,
+            "--exact" => parsed.exact = true,
+            "--wrap" => parsed.wrap_around = true,
+            "--search" => parsed.search = Some(value("--search")?),
+            "--rg-cmd" => parsed.rg_cmd = Some(value("--rg-cmd")?),
+            "--action-cmd" => parsed.action_cmd = Some(value("--action-cmd")?),
+            "--tmux" => parsed.tmux = value("--tmux")?.parse()?,
+            "--quickfix-out" => parsed.quickfix_out = Some(value("--quickfix-out")?),
+            "--clipboard" => {
+                parsed.clipboard = value("--clipboard")?.parse()?;
+            }
+            "--output-format" => {
+                parsed.output_format = value("--output-format")?.parse()?;
+            }
+            "--no-color" => parsed.no_color = true,
+            "--no-alt-screen" => parsed.no_alt_screen = true,
+            "--field-separator" => {
+                parsed.field_separator = Some(field_separator(&value("--field-separator")?)?);
+            }
+            "--max-matches" => {
+                let raw = value("--max-matches")?;
+                parsed.max_matches = Some(raw.parse().with_context(|| {
+                    format!("`--max-matches` expects a number of matches, got `{}`", raw)
+                })?);
+            }
+            "--debug-parse" => parsed.debug_parse = true,
+            "--poll-interval" => {
+                let raw = value("--poll-interval")?;
+                let millis: u64 = raw.parse().with_context(|| {
+                    format!("`--poll-interval` expects milliseconds, got `{}`", raw)
+                })?;
+                parsed.poll_interval = poll_interval(millis)?;
+            }
+            // Stdin, which is read anyway without a file
+            "-" if parsed.input.is_none() => {}
+            _ if !flag.starts_with('-') && parsed.input.is_none() => {
+                parsed.input = Some(flag.clone())
+            }
+            _ => return Err(anyhow::anyhow!("Unknown argument `{}`", flag)),
+        }
+        if inline_value.is_some() && !took_inline {
+            return Err(anyhow::anyhow!("`{}` does not take a value", flag));
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        parse_args_from(&Config::default(), args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn values_can_follow_an_equals_sign() {
+        let args = parse(&["--context=3", "--path-style", "absolute"]).unwrap();
+        assert_eq!(args.context, 3);
+        assert_eq!(args.path_style, PathStyle::Absolute);
+    }
+
+    #[test]
+    fn switches_reject_an_inline_value() {
+        let error = parse(&["--wrap=false"]).unwrap_err();
+        assert_eq!(error.to_string(), "`--wrap` does not take a value");
+        assert!(parse(&["--no-color=1"]).is_err());
+    }
+
+    #[test]
+    fn dash_reads_stdin() {
+        let args = parse(&["-", "--unique"]).unwrap();
+        assert_eq!(args.input, None);
+        assert!(args.unique);
+        assert_eq!(
+            parse(&["saved.json"]).unwrap().input.as_deref(),
+            Some("saved.json")
+        );
+    }
+}
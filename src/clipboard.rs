@@ -0,0 +1,90 @@
+use crate::{base64, open_tty};
+use anyhow::{Context, Result};
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+// Where copied text is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardBackend {
+    // Native clipboard tool, falling back to OSC52 when none is available
+    Auto,
+    // Only the native clipboard tools
+    Native,
+    // Only the OSC52 escape sequence, which the terminal forwards to the local clipboard
+    // (works over SSH)
+    Osc52,
+}
+
+impl FromStr for ClipboardBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "native" => Ok(Self::Native),
+            "osc52" => Ok(Self::Osc52),
+            _ => Err(anyhow::anyhow!(
+                "Unknown clipboard `{}`, expected `auto`, `native` or `osc52`",
+                s
+            )),
+        }
+    }
+}
+
+// Clipboard tools tried in order, with the arguments that make them read from stdin
+const CLIPBOARD_COMMANDS: [(&str, &[&str]); 5] = [
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("clip.exe", &[]),
+];
+
+// Function to copy `text` to the clipboard through the given backend
+pub fn copy_to_clipboard(text: &str, backend: ClipboardBackend) -> Result<()> {
+    match backend {
+        ClipboardBackend::Native => copy_native(text),
+        ClipboardBackend::Osc52 => copy_osc52(text),
+        ClipboardBackend::Auto => copy_native(text).or_else(|_| copy_osc52(text)),
+    }
+}
+
+// Function to copy `text` using the first available clipboard tool. Fails without side
+// effects when none is installed (e.g. headless or over SSH).
+fn copy_native(text: &str) -> Result<()> {
+    for (program, args) in CLIPBOARD_COMMANDS {
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", program)),
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .with_context(|| format!("Failed to write to {}", program))?;
+        }
+        let status = child.wait()?;
+        return if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("{} exited with {}", program, status))
+        };
+    }
+
+    Err(anyhow::anyhow!("No clipboard available"))
+}
+
+// Function to ask the terminal to set the clipboard with an OSC52 escape sequence
+fn copy_osc52(text: &str) -> Result<()> {
+    let mut tty = open_tty()?;
+    write!(tty, "\x1b]52;c;{}\x07", base64::encode(text.as_bytes()))?;
+    tty.flush().context("Failed to write OSC52 sequence")
+}
@@ -0,0 +1,338 @@
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// A single value from the config file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+// Settings loaded from `config.toml`, keyed by `[section]` name (empty for top-level keys).
+// Every key looked up is remembered, so keys nothing reads can be reported as unknown.
+#[derive(Debug, Default)]
+pub struct Config {
+    sections: HashMap<String, HashMap<String, Value>>,
+    read: RefCell<HashSet<(String, String)>>,
+}
+
+impl Config {
+    // Function to look up a raw value
+    pub fn get(&self, section: &str, key: &str) -> Option<&Value> {
+        self.read
+            .borrow_mut()
+            .insert((section.to_string(), key.to_string()));
+        self.sections.get(section)?.get(key)
+    }
+
+    // Function to fail on a key none of the settings looked up, e.g. a misspelled one. Call
+    // it once everything has been read from the config.
+    pub fn check_unknown_keys(&self) -> Result<()> {
+        let read = self.read.borrow();
+        let mut unknown: Vec<(&String, &String)> = self
+            .sections
+            .iter()
+            .flat_map(|(section, values)| values.keys().map(move |key| (section, key)))
+            .filter(|&(section, key)| !read.contains(&(section.clone(), key.clone())))
+            .collect();
+        unknown.sort();
+        match unknown.first() {
+            Some((section, key)) => Err(anyhow::anyhow!(
+                "Unknown config key `{}`",
+                key_name(section, key)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_str(&self, section: &str, key: &str) -> Result<Option<&str>> {
+        match self.get(section, key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(type_error(section, key, "a string")),
+        }
+    }
+
+    pub fn get_bool(&self, section: &str, key: &str) -> Result<Option<bool>> {
+        match self.get(section, key) {
+            None => Ok(None),
+            Some(Value::Boolean(b)) => Ok(Some(*b)),
+            Some(_) => Err(type_error(section, key, "a boolean")),
+        }
+    }
+
+    pub fn get_usize(&self, section: &str, key: &str) -> Result<Option<usize>> {
+        match self.get(section, key) {
+            None => Ok(None),
+            Some(Value::Integer(n)) => usize::try_from(*n)
+                .map(Some)
+                .map_err(|_| type_error(section, key, "a non-negative integer")),
+            Some(_) => Err(type_error(section, key, "a non-negative integer")),
+        }
+    }
+}
+
+fn type_error(section: &str, key: &str, expected: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Config key `{}` must be {}",
+        key_name(section, key),
+        expected
+    )
+}
+
+// Function to name a key the way it's written in the file, e.g. `theme.border`
+fn key_name(section: &str, key: &str) -> String {
+    if section.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", section, key)
+    }
+}
+
+// Function to get the config file location, `$XDG_CONFIG_HOME/rgnav/config.toml` falling
+// back to `~/.config/rgnav/config.toml`
+pub fn config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("rgnav").join("config.toml"))
+}
+
+// Function to load the config file. A missing file just means no overrides.
+pub fn load_config() -> Result<Config> {
+    let Some(path) = config_path() else {
+        return Ok(Config::default());
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+
+    parse_config(&contents).with_context(|| format!("Malformed config file {}", path.display()))
+}
+
+// Function to parse the subset of TOML used by the config: `[section]` headers and
+// `key = value` pairs holding strings, integers or booleans
+fn parse_config(contents: &str) -> Result<Config> {
+    let mut config = Config::default();
+    let mut section = String::new();
+
+    for (idx, raw_line) in contents.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed = if let Some(header) = line.strip_prefix('[') {
+            parse_header(header).map(|name| section = name)
+        } else {
+            parse_pair(line).map(|(key, value)| {
+                config
+                    .sections
+                    .entry(section.clone())
+                    .or_default()
+                    .insert(key, value);
+            })
+        };
+        parsed.with_context(|| format!("line {}: `{}`", idx + 1, line))?;
+    }
+
+    Ok(config)
+}
+
+fn parse_header(header: &str) -> Result<String> {
+    let (name, rest) = header
+        .split_once(']')
+        .context("Section header is missing `]`")?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Section name is empty"));
+    }
+    expect_end(rest)?;
+    Ok(name.to_string())
+}
+
+fn parse_pair(line: &str) -> Result<(String, Value)> {
+    let (key, raw_value) = line.split_once('=').context("Expected `key = value`")?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(anyhow::anyhow!("Key is empty"));
+    }
+    Ok((key.to_string(), parse_value(raw_value.trim())?))
+}
+
+fn parse_value(raw: &str) -> Result<Value> {
+    if let Some(rest) = raw.strip_prefix('"') {
+        // Basic string with backslash escapes
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    expect_end(&rest[i + 1..])?;
+                    return Ok(Value::String(value));
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    other => {
+                        return Err(anyhow::anyhow!(
+                            "Unsupported escape `\\{}`",
+                            other.unwrap_or(' ')
+                        ))
+                    }
+                },
+                _ => value.push(c),
+            }
+        }
+        return Err(anyhow::anyhow!("Unterminated string"));
+    }
+
+    if let Some(rest) = raw.strip_prefix('\'') {
+        // Literal string, no escapes
+        let (value, rest) = rest.split_once('\'').context("Unterminated string")?;
+        expect_end(rest)?;
+        return Ok(Value::String(value.to_string()));
+    }
+
+    let token = raw.split('#').next().unwrap_or_default().trim();
+    let unsupported = || anyhow::anyhow!("Unsupported value `{}`", token);
+    match token {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => {
+            // Underscores may only separate digits, as in `1_000`
+            let digits = token.trim_start_matches(['+', '-']);
+            if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+                return Err(unsupported());
+            }
+            token
+                .replace('_', "")
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| unsupported())
+        }
+    }
+}
+
+// Function to check that only whitespace or a comment follows a value
+fn expect_end(rest: &str) -> Result<()> {
+    let rest = rest.trim();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Unexpected trailing `{}`", rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(raw: &str) -> Value {
+        let config = parse_config(&format!("key = {}", raw)).unwrap();
+        config.get("", "key").unwrap().clone()
+    }
+
+    fn error(contents: &str) -> String {
+        format!("{:#}", parse_config(contents).unwrap_err())
+    }
+
+    #[test]
+    fn basic_strings_unescape() {
+        assert_eq!(
+            value(r#""tab\there \"quoted\" back\\slash\n""#),
+            Value::String("tab\there \"quoted\" back\\slash\n".to_string())
+        );
+        assert!(error(r#"key = "\x41""#).contains("Unsupported escape `\\x`"));
+    }
+
+    #[test]
+    fn literal_strings_keep_backslashes() {
+        assert_eq!(
+            value(r"'C:\a\b' # path"),
+            Value::String(r"C:\a\b".to_string())
+        );
+    }
+
+    #[test]
+    fn comments_may_follow_values() {
+        assert_eq!(value("true # on"), Value::Boolean(true));
+        assert_eq!(value("12# twelve"), Value::Integer(12));
+        assert_eq!(
+            value(r#""a # not a comment" # comment"#),
+            Value::String("a # not a comment".to_string())
+        );
+        assert!(error(r#"key = "a" b"#).contains("Unexpected trailing `b`"));
+    }
+
+    #[test]
+    fn integers_take_underscores_between_digits() {
+        assert_eq!(value("1_000"), Value::Integer(1000));
+        assert_eq!(value("-5"), Value::Integer(-5));
+        for raw in ["1_", "_1", "1__0", "-_1", "1.5", "fast"] {
+            assert!(
+                error(&format!("key = {}", raw)).contains("Unsupported value"),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn negative_counts_are_rejected_when_read() {
+        let config = parse_config("context = -1").unwrap();
+        let error = config.get_usize("", "context").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Config key `context` must be a non-negative integer"
+        );
+    }
+
+    #[test]
+    fn unterminated_strings_and_sections_fail_with_their_line() {
+        assert_eq!(
+            error("a = 1\nkey = \"open"),
+            "line 2: `key = \"open`: Unterminated string"
+        );
+        assert!(error("key = 'open").contains("Unterminated string"));
+        assert_eq!(
+            error("[theme"),
+            "line 1: `[theme`: Section header is missing `]`"
+        );
+    }
+
+    #[test]
+    fn sections_hold_their_own_keys() {
+        let config = parse_config("context = 3\n\n[theme]\npreset = 'light'").unwrap();
+        assert_eq!(config.get_usize("", "context").unwrap(), Some(3));
+        assert_eq!(config.get_str("theme", "preset").unwrap(), Some("light"));
+        assert_eq!(config.get_str("", "preset").unwrap(), None);
+    }
+
+    #[test]
+    fn keys_nothing_reads_are_unknown() {
+        let config =
+            parse_config("context = 3\npreview_width = -1\n[theme]\nborder = 'red'").unwrap();
+        config.get_usize("", "context").unwrap();
+        config.get_str("theme", "border").unwrap();
+        assert_eq!(
+            config.check_unknown_keys().unwrap_err().to_string(),
+            "Unknown config key `preview_width`"
+        );
+        config.get("", "preview_width");
+        assert!(config.check_unknown_keys().is_ok());
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use crossterm::event::{self, Event};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+// How long the input thread waits for a key before checking whether it should pause. This
+// bounds how long handing the terminal to the editor waits.
+const INPUT_POLL: Duration = Duration::from_millis(50);
+
+enum Message {
+    Input(io::Result<Event>),
+    Wake,
+}
+
+// Terminal events read on a background thread, together with wake-ups from the threads
+// loading matches, so the main loop sleeps until there's something to handle
+pub struct Events {
+    receiver: Receiver<Message>,
+    waker: Waker,
+    input: Thread,
+    // Set while the terminal belongs to another program
+    paused: Arc<AtomicBool>,
+    // Held by the input thread while it waits for and reads an event
+    reading: Arc<Mutex<()>>,
+}
+
+// Handle for a background thread to wake the main loop when it has something new.
+// Wake-ups are coalesced until the main loop gets to them.
+#[derive(Clone)]
+pub struct Waker {
+    sender: Sender<Message>,
+    pending: Arc<AtomicBool>,
+}
+
+impl Waker {
+    // Function to get a waker nobody listens to, for reading matches without the UI
+    pub fn detached() -> Self {
+        let (sender, _) = mpsc::channel();
+        Self {
+            sender,
+            pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn wake(&self) {
+        if !self.pending.swap(true, Ordering::AcqRel) {
+            let _ = self.sender.send(Message::Wake);
+        }
+    }
+}
+
+// While this lives the input thread leaves the terminal alone
+pub struct Paused<'a> {
+    events: &'a Events,
+    _reading: MutexGuard<'a, ()>,
+}
+
+impl Drop for Paused<'_> {
+    fn drop(&mut self) {
+        self.events.paused.store(false, Ordering::Release);
+        self.events.input.unpark();
+    }
+}
+
+impl Events {
+    // Function to start reading the terminal. `paused` is set for as long as the terminal is
+    // handed over, so others, like the signal handler, can tell who owns it.
+    pub fn new(paused: Arc<AtomicBool>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let reading = Arc::new(Mutex::new(()));
+
+        let input_sender = sender.clone();
+        let input_paused = Arc::clone(&paused);
+        let input_reading = Arc::clone(&reading);
+        let input = thread::spawn(move || loop {
+            if input_paused.load(Ordering::Acquire) {
+                thread::park();
+                continue;
+            }
+            let _reading = input_reading.lock().unwrap_or_else(|e| e.into_inner());
+            // Paused while waiting for the lock
+            if input_paused.load(Ordering::Acquire) {
+                continue;
+            }
+            let event = match event::poll(INPUT_POLL) {
+                Ok(true) => event::read(),
+                Ok(false) => continue,
+                Err(e) => Err(e),
+            };
+            let failed = event.is_err();
+            // The main loop is gone
+            if input_sender.send(Message::Input(event)).is_err() || failed {
+                break;
+            }
+        })
+        .thread()
+        .clone();
+
+        Self {
+            receiver,
+            waker: Waker {
+                sender,
+                pending: Arc::new(AtomicBool::new(false)),
+            },
+            input,
+            paused,
+            reading,
+        }
+    }
+
+    // Function to get a handle that wakes up `next`
+    pub fn waker(&self) -> Waker {
+        self.waker.clone()
+    }
+
+    // Function to wait up to `timeout` for the next terminal event. Returns `None` when the
+    // time is up or a background thread woke the loop.
+    pub fn next(&self, timeout: Duration) -> Result<Option<Event>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(Message::Input(event)) => Ok(Some(event.context("Failed to read input")?)),
+            Ok(Message::Wake) => {
+                self.waker.pending.store(false, Ordering::Release);
+                Ok(None)
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(anyhow::anyhow!("Input thread stopped")),
+        }
+    }
+
+    // Function to stop reading the terminal, e.g. while an editor runs in it, until the
+    // returned guard is dropped. Waits for a read in progress to finish.
+    pub fn pause(&self) -> Paused<'_> {
+        self.paused.store(true, Ordering::Release);
+        Paused {
+            events: self,
+            _reading: self.reading.lock().unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+}
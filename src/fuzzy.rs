@@ -0,0 +1,82 @@
+// Score for every matched character
+const SCORE_MATCH: i64 = 16;
+// Bonus when a character directly follows the previous matched one
+const BONUS_CONSECUTIVE: i64 = 24;
+// Bonus when a character starts a path component or word
+const BONUS_BOUNDARY: i64 = 20;
+// Penalty per skipped character between two matched ones
+const PENALTY_GAP: i64 = 1;
+
+// Function to fuzzy match `query` against `text` fzf-style: every query character has to
+// appear in order. Returns the score (higher is better) and the character positions that
+// matched, or `None` when `text` doesn't match. Case-insensitive unless `query` has
+// uppercase characters.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let text: Vec<char> = text.chars().collect();
+
+    let mut score = 0;
+    let mut positions = Vec::new();
+    let mut next = 0;
+    for q in query.chars().map(fold) {
+        let pos = next + text[next..].iter().position(|&c| fold(c) == q)?;
+
+        score += SCORE_MATCH;
+        match positions.last() {
+            Some(&last) if last + 1 == pos => score += BONUS_CONSECUTIVE,
+            Some(&last) => score -= PENALTY_GAP * (pos - last - 1) as i64,
+            None => {}
+        }
+        if pos == 0 || is_boundary(text[pos - 1], text[pos]) {
+            score += BONUS_BOUNDARY;
+        }
+
+        positions.push(pos);
+        next = pos + 1;
+    }
+
+    Some((score, positions))
+}
+
+fn is_boundary(prev: char, c: char) -> bool {
+    matches!(prev, '/' | '\\' | '_' | '-' | '.' | ' ') || (prev.is_lowercase() && c.is_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn characters_have_to_appear_in_order() {
+        assert_eq!(fuzzy_match("mn", "src/main.rs").unwrap().1, [4, 7]);
+        assert!(fuzzy_match("nm", "main").is_none());
+        assert!(fuzzy_match("x", "src/main.rs").is_none());
+    }
+
+    #[test]
+    fn case_is_ignored_until_the_query_has_uppercase() {
+        assert!(fuzzy_match("readme", "README.md").is_some());
+        assert!(fuzzy_match("Readme", "readme.md").is_none());
+        assert_eq!(fuzzy_match("RM", "README.md").unwrap().1, [0, 4]);
+    }
+
+    #[test]
+    fn positions_count_characters_not_bytes() {
+        assert_eq!(fuzzy_match("dé", "día/désolé").unwrap().1, [0, 5]);
+    }
+
+    #[test]
+    fn consecutive_and_boundary_matches_score_higher() {
+        let score = |text| fuzzy_match("main", text).unwrap().0;
+        assert!(score("src/main.rs") > score("src/my_axe_in.rs"));
+        assert!(score("src/main.rs") > score("src/domain.rs"));
+        assert!(score("FooMain.rs") > score("foomain.rs"));
+    }
+}
@@ -0,0 +1,494 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// Something the user can trigger from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    Open,
+    Quit,
+    ToggleWrap,
+    TogglePreview,
+    MoreContext,
+    LessContext,
+    CopyPath,
+    CopyLocation,
+    CopyLine,
+    ShrinkList,
+    GrowList,
+    ScrollPreviewDown,
+    ScrollPreviewUp,
+    Reverse,
+    CycleSort,
+    FilterExtension,
+    ToggleBasename,
+    Search,
+    ToggleMark,
+    ExportQuickfix,
+    NextFile,
+    PreviousFile,
+    ToggleLineNumbers,
+    CyclePathStyle,
+    OpenDirectory,
+    ScrollPreviewRight,
+    ScrollPreviewLeft,
+    Reload,
+    FindInPreview,
+    NextPreviewHit,
+    PreviousPreviewHit,
+    ToggleTree,
+    JumpBack,
+    Hide,
+    Unhide,
+    Confirm,
+    Help,
+    Filter,
+}
+
+impl Action {
+    // Every action with its name in the `[keys]` config section, its default bindings and a
+    // description for the help overlay. Bindings are comma-separated alternatives; spaces
+    // separate keys of a sequence.
+    const ALL: [(Action, &'static str, &'static str, &'static str); 44] = [
+        (Action::Up, "up", "Up, k", "Move up"),
+        (Action::Down, "down", "Down, j", "Move down"),
+        (Action::PageDown, "page_down", "ctrl-f", "Page down"),
+        (Action::PageUp, "page_up", "ctrl-b", "Page up"),
+        (Action::Top, "top", "g g", "Jump to the first result"),
+        (Action::Bottom, "bottom", "G", "Jump to the last result"),
+        (
+            Action::Open,
+            "open",
+            "Enter",
+            "Open in $EDITOR / confirm selection",
+        ),
+        (Action::Quit, "quit", "q, Esc", "Quit"),
+        (
+            Action::ToggleWrap,
+            "toggle_wrap",
+            "w",
+            "Toggle preview line wrapping",
+        ),
+        (
+            Action::TogglePreview,
+            "toggle_preview",
+            "p",
+            "Show or hide the preview",
+        ),
+        (
+            Action::MoreContext,
+            "more_context",
+            "+",
+            "Show more preview context",
+        ),
+        (
+            Action::LessContext,
+            "less_context",
+            "-",
+            "Show less preview context",
+        ),
+        (Action::CopyPath, "copy_path", "y", "Copy path"),
+        (Action::CopyLocation, "copy_location", "Y", "Copy path:line"),
+        (Action::CopyLine, "copy_line", "c", "Copy the matched line"),
+        (Action::Filter, "filter", "/", "Filter results"),
+        (
+            Action::ShrinkList,
+            "shrink_list",
+            "<",
+            "Give the list less room",
+        ),
+        (
+            Action::GrowList,
+            "grow_list",
+            ">",
+            "Give the list more room",
+        ),
+        (
+            Action::ScrollPreviewDown,
+            "scroll_preview_down",
+            "ctrl-d",
+            "Scroll the preview down",
+        ),
+        (
+            Action::ScrollPreviewUp,
+            "scroll_preview_up",
+            "ctrl-u",
+            "Scroll the preview up",
+        ),
+        (
+            Action::Reverse,
+            "reverse",
+            "r",
+            "Reverse the order of results",
+        ),
+        (
+            Action::CycleSort,
+            "cycle_sort",
+            "s",
+            "Sort by input order, path or line number",
+        ),
+        (
+            Action::FilterExtension,
+            "filter_extension",
+            "e",
+            "Filter results by file extension",
+        ),
+        (
+            Action::ToggleBasename,
+            "toggle_basename",
+            "f",
+            "Show file names only or full paths",
+        ),
+        (Action::Search, "search", "S", "Edit the --search query"),
+        (
+            Action::ToggleMark,
+            "toggle_mark",
+            "space",
+            "Mark or unmark the match for opening together",
+        ),
+        (
+            Action::ExportQuickfix,
+            "export_quickfix",
+            "Q",
+            "Write the listed matches as a quickfix list",
+        ),
+        (Action::NextFile, "next_file", "}", "Jump to the next file"),
+        (
+            Action::PreviousFile,
+            "previous_file",
+            "{",
+            "Jump to the previous file",
+        ),
+        (
+            Action::ToggleLineNumbers,
+            "toggle_line_numbers",
+            "#",
+            "Toggle preview line numbers",
+        ),
+        (
+            Action::CyclePathStyle,
+            "cycle_path_style",
+            "a",
+            "Show paths as given, relative or absolute",
+        ),
+        (
+            Action::OpenDirectory,
+            "open_directory",
+            "o",
+            "Open the match's directory in the file manager",
+        ),
+        (
+            Action::ScrollPreviewRight,
+            "scroll_preview_right",
+            "l",
+            "Scroll the preview right",
+        ),
+        (
+            Action::ScrollPreviewLeft,
+            "scroll_preview_left",
+            "h",
+            "Scroll the preview left",
+        ),
+        (
+            Action::Reload,
+            "reload",
+            "R",
+            "Rerun the search or --rg-cmd",
+        ),
+        (
+            Action::FindInPreview,
+            "find_in_preview",
+            "F",
+            "Find text in the preview",
+        ),
+        (
+            Action::NextPreviewHit,
+            "next_preview_hit",
+            "n",
+            "Scroll to the next occurrence in the preview",
+        ),
+        (
+            Action::PreviousPreviewHit,
+            "previous_preview_hit",
+            "N",
+            "Scroll to the previous occurrence in the preview",
+        ),
+        (
+            Action::ToggleTree,
+            "toggle_tree",
+            "t",
+            "Show the results as a directory tree or a flat list",
+        ),
+        (
+            Action::JumpBack,
+            "jump_back",
+            "ctrl-o",
+            "Jump back to the previously opened match",
+        ),
+        (Action::Hide, "hide", "d", "Hide the match from the list"),
+        (
+            Action::Unhide,
+            "unhide",
+            "u",
+            "Bring back the last hidden match",
+        ),
+        (
+            Action::Confirm,
+            "confirm",
+            "Tab",
+            "Print the selection and exit",
+        ),
+        (Action::Help, "help", "?", "Toggle this help"),
+    ];
+}
+
+// A key together with the modifiers held while pressing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyCombo {
+    fn from_event(key: KeyEvent) -> Self {
+        // Shift is already reflected in the character itself (`G`, `+`, ...)
+        let modifiers = match key.code {
+            KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+        Self {
+            code: key.code,
+            modifiers,
+        }
+    }
+
+    // Function to parse a key such as `j`, `G`, `Enter`, `PageDown` or `ctrl-c`
+    fn parse(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            let (modifier, len) = if lower.starts_with("ctrl-") {
+                (KeyModifiers::CONTROL, 5)
+            } else if lower.starts_with("alt-") {
+                (KeyModifiers::ALT, 4)
+            } else {
+                break;
+            };
+            // A lone `-` after the prefix is the key itself, e.g. `ctrl--`
+            if rest.len() == len {
+                break;
+            }
+            modifiers |= modifier;
+            rest = &rest[len..];
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "space" => KeyCode::Char(' '),
+                "comma" => KeyCode::Char(','),
+                "backspace" => KeyCode::Backspace,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) => KeyCode::F(n),
+                    None => return Err(anyhow::anyhow!("Unknown key `{}`", s)),
+                },
+            },
+        };
+
+        Ok(Self { code, modifiers })
+    }
+}
+
+// Key sequences mapped to the actions they trigger
+#[derive(Debug)]
+pub struct KeyBindings {
+    bindings: Vec<(Vec<KeyCombo>, Action)>,
+    // Binding spec and description of every action, for the help overlay
+    help: Vec<(String, &'static str)>,
+    // Keys typed so far that are a prefix of a longer sequence
+    pending: Vec<KeyCombo>,
+}
+
+impl KeyBindings {
+    // Function to build the bindings, letting the `[keys]` config section replace the
+    // defaults of individual actions
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut bindings = Vec::new();
+        let mut help = Vec::new();
+        for (action, name, default, description) in Action::ALL {
+            let spec = config.get_str("keys", name)?.unwrap_or(default);
+            help.push((spec.to_string(), description));
+            for alternative in spec.split(',') {
+                let sequence = alternative
+                    .split_whitespace()
+                    .map(KeyCombo::parse)
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(|| format!("Invalid binding for `keys.{}`", name))?;
+                if sequence.is_empty() {
+                    return Err(anyhow::anyhow!("Empty binding for `keys.{}`", name));
+                }
+                bindings.push((sequence, action));
+            }
+        }
+
+        Ok(Self {
+            bindings,
+            help,
+            pending: Vec::new(),
+        })
+    }
+
+    // Function to list the bindings of every action alongside its description
+    pub fn help_entries(&self) -> &[(String, &'static str)] {
+        &self.help
+    }
+
+    // Function to feed a key press through the bindings, returning the action it completes.
+    // Keys that only start a sequence are held until the sequence is finished or broken.
+    pub fn handle(&mut self, key: KeyEvent) -> Option<Action> {
+        let combo = KeyCombo::from_event(key);
+        self.pending.push(combo);
+
+        if let Some(action) = self.lookup(&self.pending) {
+            self.pending.clear();
+            return Some(action);
+        }
+        if self.is_prefix(&self.pending) {
+            return None;
+        }
+
+        // The sequence was broken, so let this key start over on its own
+        let retry = self.pending.len() > 1;
+        self.pending.clear();
+        if retry {
+            self.handle(key)
+        } else {
+            None
+        }
+    }
+
+    fn lookup(&self, sequence: &[KeyCombo]) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(keys, _)| keys == sequence)
+            .map(|&(_, action)| action)
+    }
+
+    fn is_prefix(&self, sequence: &[KeyCombo]) -> bool {
+        self.bindings
+            .iter()
+            .any(|(keys, _)| keys.len() > sequence.len() && keys.starts_with(sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(code: KeyCode, modifiers: KeyModifiers) -> KeyCombo {
+        KeyCombo { code, modifiers }
+    }
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_characters_names_and_function_keys() {
+        let none = KeyModifiers::NONE;
+        assert_eq!(
+            KeyCombo::parse("j").unwrap(),
+            combo(KeyCode::Char('j'), none)
+        );
+        assert_eq!(
+            KeyCombo::parse("G").unwrap(),
+            combo(KeyCode::Char('G'), none)
+        );
+        assert_eq!(
+            KeyCombo::parse("PageDown").unwrap(),
+            combo(KeyCode::PageDown, none)
+        );
+        assert_eq!(
+            KeyCombo::parse("enter").unwrap(),
+            combo(KeyCode::Enter, none)
+        );
+        assert_eq!(
+            KeyCombo::parse("Space").unwrap(),
+            combo(KeyCode::Char(' '), none)
+        );
+        assert_eq!(
+            KeyCombo::parse("comma").unwrap(),
+            combo(KeyCode::Char(','), none)
+        );
+        assert_eq!(KeyCombo::parse("F5").unwrap(), combo(KeyCode::F(5), none));
+    }
+
+    #[test]
+    fn parses_modifiers_in_any_case() {
+        assert_eq!(
+            KeyCombo::parse("ctrl-c").unwrap(),
+            combo(KeyCode::Char('c'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyCombo::parse("Ctrl-Alt-Up").unwrap(),
+            combo(KeyCode::Up, KeyModifiers::CONTROL | KeyModifiers::ALT)
+        );
+        // The key after the prefix can be a dash itself
+        assert_eq!(
+            KeyCombo::parse("ctrl--").unwrap(),
+            combo(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for spec in ["", "ctrl-", "hyper-x", "Fx", "enterr"] {
+            assert!(KeyCombo::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn shift_is_part_of_the_character() {
+        let mut bindings = KeyBindings::from_config(&Config::default()).unwrap();
+        assert_eq!(
+            bindings.handle(press(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            Some(Action::Bottom)
+        );
+    }
+
+    #[test]
+    fn sequences_complete_or_start_over() {
+        let mut bindings = KeyBindings::from_config(&Config::default()).unwrap();
+        let none = KeyModifiers::NONE;
+        assert_eq!(bindings.handle(press(KeyCode::Char('g'), none)), None);
+        assert_eq!(
+            bindings.handle(press(KeyCode::Char('g'), none)),
+            Some(Action::Top)
+        );
+        // A broken sequence lets the key that broke it count on its own
+        assert_eq!(bindings.handle(press(KeyCode::Char('g'), none)), None);
+        assert_eq!(
+            bindings.handle(press(KeyCode::Char('j'), none)),
+            Some(Action::Down)
+        );
+    }
+}
@@ -0,0 +1,30 @@
+// rgnav's match browser as a library, for embedding it in other terminal apps. The `rgnav`
+// binary is a thin wrapper around `App`.
+mod app;
+mod base64;
+pub mod cli;
+mod clipboard;
+pub mod config;
+mod events;
+mod fuzzy;
+pub mod keys;
+pub mod preview;
+pub mod rg_matches;
+mod state;
+pub mod theme;
+mod tree;
+
+pub use app::{restore_terminal, App, Exit, Job, Step};
+pub use rg_matches::{MatchData, RgMatch};
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+
+// Function to open the controlling terminal, since stdin and stdout may both be redirected
+pub fn open_tty() -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("Failed to open /dev/tty")
+}
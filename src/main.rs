@@ -1,188 +1,73 @@
-mod rg_matches;
-
-use rg_matches::get_rg_matches;
-
 use anyhow::{Context, Result};
-use crossterm::{
-    event::{self, Event, KeyCode},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
-    Terminal,
-};
-use std::io::{self};
-use std::process::Command;
-
-// Struct to ensure the terminal is restored on exit
-struct TerminalCleanup;
-
-impl TerminalCleanup {
-    fn new() -> Result<Self> {
-        enable_raw_mode().context("Failed to enable raw mode")?;
-        execute!(io::stdout(), EnterAlternateScreen).context("Failed to enter alternate screen")?;
-        Ok(Self)
-    }
-}
-
-impl Drop for TerminalCleanup {
-    fn drop(&mut self) {
-        let _ = disable_raw_mode();
-        let _ = execute!(io::stdout(), LeaveAlternateScreen);
-    }
+use rgnav::cli::{parse_args, print_info_if_requested};
+use rgnav::config::load_config;
+use rgnav::{restore_terminal, App, Exit};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// How often a signal waiting for the terminal checks whether it has been handed back
+const HAND_BACK_POLL: Duration = Duration::from_millis(50);
+
+// Function to restore the terminal before a panic message is printed, so it isn't lost on
+// the alternate screen and the shell isn't left in raw mode
+fn install_panic_hook(alt_screen: bool) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal(alt_screen);
+        default_hook(info);
+    }));
 }
 
-fn main() -> Result<()> {
-    // Initialize the TerminalCleanup struct to manage terminal state
-
-    // Enter alternate screen and enable raw mode
-    enable_raw_mode().context("Failed to enable raw mode")?;
-
-    let _cleanup = TerminalCleanup::new()?;
-    let rg_matches = get_rg_matches()?;
-
-    let mut terminal = setup_terminal()?;
-
-    let mut selected_idx = 0;
-    loop {
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
-                .split(f.area());
-
-            // Define highlight style for the selected item
-            let highlight_style = Style::default()
-                .bg(Color::Blue)
-                .fg(Color::White)
-                .add_modifier(Modifier::BOLD);
-
-            let items: Vec<ListItem> = rg_matches
-                .iter()
-                .map(|m| {
-                    ListItem::new(
-                        m.data
-                            .as_ref()
-                            .map(|data| data.path.text.clone())
-                            .unwrap_or_default(),
-                    )
-                })
-                .collect();
-
-            let list = List::new(items)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title("Search Results"),
-                )
-                .highlight_style(highlight_style); // Apply highlight style
-
-            f.render_stateful_widget(list, chunks[0], &mut create_list_state(selected_idx));
-
-            if let Some(data) = rg_matches.get(selected_idx).and_then(|m| m.data.as_ref()) {
-                // Capture `bat` output for file preview with context around the match line
-                let preview_text = get_file_preview(&data.path.text, data.line_number)
-                    .unwrap_or_else(|_| "Error loading preview".into());
-
-                let preview = Paragraph::new(preview_text)
-                    .block(Block::default().borders(Borders::ALL).title("Code Preview"));
-                f.render_widget(preview, chunks[1]);
+// Function to restore the terminal and exit when rgnav is interrupted or terminated from
+// outside, e.g. by `kill`, instead of dying with the shell left in raw mode. Exits with the
+// conventional 128 + signal number. While `handed_over` is set another program owns the
+// terminal: Ctrl-C reaches that program too and is left to it, like a shell leaves it to the
+// foreground job, and other signals wait until the terminal is back.
+fn install_signal_handler(alt_screen: bool, handed_over: Arc<AtomicBool>) -> Result<()> {
+    let mut signals =
+        Signals::new([SIGINT, SIGTERM]).context("Failed to install signal handlers")?;
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if signal == SIGINT && handed_over.load(Ordering::Acquire) {
+                continue;
             }
-        })?;
-
-        // Handle key events
-        if event::poll(std::time::Duration::from_millis(200))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Up => {
-                        if selected_idx > 0 {
-                            selected_idx -= 1;
-                        }
-                    }
-                    KeyCode::Down => {
-                        if selected_idx < rg_matches.len() - 1 {
-                            selected_idx += 1;
-                        }
-                    }
-                    KeyCode::Char('q') => break, // Exit on 'q' key
-                    KeyCode::Esc => break,
-                    _ => {}
-                }
+            while handed_over.load(Ordering::Acquire) {
+                std::thread::sleep(HAND_BACK_POLL);
             }
+            restore_terminal(alt_screen);
+            std::process::exit(128 + signal);
         }
-    }
-
+    });
     Ok(())
 }
 
-fn setup_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>> {
-    let stdout = io::stdout();
-    let backend = CrosstermBackend::new(stdout);
-    let terminal = Terminal::new(backend)?;
-    Ok(terminal)
-}
-
-// Function to create the list state with the selected index
-fn create_list_state(selected_idx: usize) -> ratatui::widgets::ListState {
-    let mut state = ratatui::widgets::ListState::default();
-    state.select(Some(selected_idx));
-    state
-}
-
-use ansi_to_tui::IntoText;
-use ratatui::text::Text;
-
-// Maximum number of characters per line
-const MAX_LINE_LENGTH: usize = 80;
-
-// Function to get preview of file content around the specific line using `bat`
-fn get_file_preview(file_path: &str, line_number: usize) -> Result<Text> {
-    let start_line = if line_number > 15 {
-        line_number - 15
-    } else {
-        1
-    };
-    let end_line = line_number + 15;
-
-    // Use `bat` with color enabled
-    let output = Command::new("bat")
-        .args([
-            "--style",
-            "plain",
-            "--paging",
-            "never",
-            "--color",
-            "always", // Enable color for ANSI escape sequences
-            "--line-range",
-            &format!("{}:{}", start_line, end_line), // Context range around the match
-            file_path,
-        ])
-        .output()
-        .context("Failed to execute bat")?;
+fn main() -> Result<()> {
+    // Parse arguments first so usage errors print to a normal terminal
+    print_info_if_requested();
+    let config = load_config()?;
+    let args = parse_args(&config)?;
+    let debug_parse = args.debug_parse;
+    let alt_screen = !args.no_alt_screen;
+    let app = App::new(args, &config)?;
+    // The flags, keys and theme have all been read from the config by now
+    config.check_unknown_keys()?;
+    if debug_parse {
+        return app.debug_parse();
+    }
 
-    if output.status.success() {
-        // Process each line to truncate it to the max length
-        let preview_text = String::from_utf8_lossy(&output.stdout)
-            .lines()
-            .map(|line| {
-                let mut truncated = line.to_string();
-                truncated.truncate(MAX_LINE_LENGTH); // Limit each line length
-                truncated
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
+    install_panic_hook(alt_screen);
+    install_signal_handler(alt_screen, app.terminal_handed_over())?;
 
-        preview_text
-            .into_text()
-            .map_err(|e| anyhow::anyhow!("Failed to parse ANSI: {}", e))
-    } else {
-        Err(anyhow::anyhow!(
-            "Error running bat: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ))
+    match app.run()? {
+        Exit::Selected(selection) => {
+            println!("{}", selection);
+            Ok(())
+        }
+        // Quitting without a selection exits non-zero so shell integrations can tell
+        Exit::Quit => std::process::exit(1),
+        Exit::Interrupted => std::process::exit(128 + SIGINT),
     }
 }
@@ -0,0 +1,940 @@
+use crate::rg_matches::MatchData;
+use ansi_to_tui::IntoText;
+use anyhow::{Context, Result};
+use lru::LruCache;
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// Maximum number of display columns per line
+const MAX_LINE_LENGTH: usize = 80;
+
+// Files with a NUL byte this close to the start are treated as binary, like git does
+const BINARY_SNIFF_LEN: usize = 8000;
+
+pub const BYTES_PER_MB: u64 = 1024 * 1024;
+
+// Style of notes shown instead of a preview, such as for binary files
+const PLACEHOLDER_STYLE: Style = Style::new()
+    .fg(Color::DarkGray)
+    .add_modifier(Modifier::ITALIC);
+
+// Occurrences of the text searched for in the preview
+const PREVIEW_SEARCH_STYLE: Style = Style::new()
+    .fg(Color::Black)
+    .bg(Color::Yellow)
+    .add_modifier(Modifier::BOLD);
+
+// Style of the line-number gutter in the preview
+const LINE_NUMBER_STYLE: Style = Style::new().fg(Color::DarkGray);
+
+// Style of the `⋯ N lines above/below` markers at the edges of the preview
+const FOLD_MARKER_STYLE: Style = Style::new().fg(Color::DarkGray);
+
+// How preview text gets syntax highlighted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Highlighter {
+    // Shell out to `bat` for colored output
+    Bat,
+    // Read the file directly and show it as plain text
+    None,
+}
+
+impl FromStr for Highlighter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bat" => Ok(Self::Bat),
+            "none" => Ok(Self::None),
+            _ => Err(anyhow::anyhow!(
+                "Unknown highlighter `{}`, expected `bat` or `none`",
+                s
+            )),
+        }
+    }
+}
+
+// Command rendering previews in place of `bat`, e.g. `batcat --color=always -r {start}:{end}
+// {file}`. Arguments are split on whitespace; `{file}`, `{start}`, `{end}` and `{line}` are
+// replaced with the file path, the first and last line to show and the matched line. The
+// output should start at `{start}`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PreviewCommand {
+    args: Vec<String>,
+}
+
+impl FromStr for PreviewCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let args: Vec<String> = s.split_whitespace().map(str::to_string).collect();
+        if args.is_empty() {
+            return Err(anyhow::anyhow!("The preview command is empty"));
+        }
+        if !args.iter().any(|arg| arg.contains("{file}")) {
+            return Err(anyhow::anyhow!(
+                "The preview command `{}` has no `{{file}}` placeholder",
+                s
+            ));
+        }
+        Ok(Self { args })
+    }
+}
+
+impl PreviewCommand {
+    // Function to run the command for the given line range and capture its output
+    fn run(
+        &self,
+        file_path: &Path,
+        start_line: usize,
+        end_line: usize,
+        line_number: usize,
+    ) -> Result<String> {
+        let args = self.args.iter().map(|arg| {
+            // Pass a bare `{file}` through untouched so non-UTF-8 paths survive
+            if arg == "{file}" {
+                return file_path.as_os_str().to_owned();
+            }
+            arg.replace("{file}", &file_path.to_string_lossy())
+                .replace("{start}", &start_line.to_string())
+                .replace("{end}", &end_line.to_string())
+                .replace("{line}", &line_number.to_string())
+                .into()
+        });
+        let output = Command::new(&self.args[0])
+            .args(args.skip(1))
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to run preview command `{}`", self.args[0]))?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(anyhow::anyhow!(
+                "Error running {} ({}): {}",
+                self.args[0],
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ))
+        }
+    }
+}
+
+// Options controlling how `get_file_preview` renders a match
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PreviewOptions {
+    // Lines shown before and after the matched line
+    pub context: usize,
+    // Keep long lines whole so the preview widget can soft-wrap or scroll sideways through
+    // them
+    pub wrap: bool,
+    pub highlighter: Highlighter,
+    // Files larger than this many bytes get a placeholder instead of a preview
+    pub max_file_size: u64,
+    // Prefix every line with its line number in the file
+    pub line_numbers: bool,
+    // Say how many lines of the file are left out above and below the preview
+    pub fold_markers: bool,
+    // Background used to mark the matched line, from the theme
+    pub match_line_style: Style,
+    // Theme `bat` highlights with, from `--bat-theme`
+    pub bat_theme: Option<String>,
+    // Command used instead of `bat`, from `--preview-command`
+    pub preview_command: Option<PreviewCommand>,
+    // Colors are turned off, so neither `bat` nor `git` may emit any
+    pub no_color: bool,
+    // Preview changed files as a diff against HEAD, with `--git-context`
+    pub git_context: bool,
+    // Preview the file a symlinked path points at, with `--follow-symlinks`
+    pub follow_symlinks: bool,
+}
+
+// Lines of context asked of `git diff`, enough to always cover the whole file
+const WHOLE_FILE_CONTEXT: usize = 1_000_000;
+
+// Set once `bat` turns out not to be installed, so later previews skip straight to plain text
+pub static BAT_MISSING: AtomicBool = AtomicBool::new(false);
+
+// Maximum number of rendered previews kept in the cache
+const PREVIEW_CACHE_SIZE: usize = 128;
+
+// Rendered previews keyed by file path, line number, the file's modification time and the
+// options they were rendered with, shared with the prefetch thread
+pub type PreviewCache = Arc<Mutex<LruCache<CacheKey, Preview>>>;
+
+type CacheKey = (String, usize, Option<SystemTime>, PreviewOptions);
+
+// A rendered preview along with the file line each of its lines shows. Lines that aren't
+// lines of the file, such as fold markers and lines removed since HEAD, map to `None`.
+#[derive(Debug, Clone, Default)]
+pub struct Preview {
+    pub text: Text<'static>,
+    pub line_map: Vec<Option<usize>>,
+}
+
+impl Preview {
+    // Function to get the index of the preview line showing `line_number` of the file
+    pub fn row_of(&self, line_number: usize) -> Option<usize> {
+        self.line_map
+            .iter()
+            .position(|&line| line == Some(line_number))
+    }
+}
+
+// Notes and error messages shown in place of the file
+impl From<Text<'static>> for Preview {
+    fn from(text: Text<'static>) -> Self {
+        let line_map = vec![None; text.lines.len()];
+        Self { text, line_map }
+    }
+}
+
+// Function to create an empty preview cache
+pub fn new_preview_cache() -> PreviewCache {
+    let size = NonZeroUsize::new(PREVIEW_CACHE_SIZE).expect("cache size is non-zero");
+    Arc::new(Mutex::new(LruCache::new(size)))
+}
+
+// Function to lock the cache. A panic while it was held can't leave it half-updated, so a
+// poisoned lock is still usable.
+fn lock(cache: &PreviewCache) -> MutexGuard<'_, LruCache<CacheKey, Preview>> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Function to get preview of file content around the specific line, reusing a cached
+// rendering when the same match was previewed before with the same options
+pub fn get_file_preview(
+    cache: &PreviewCache,
+    data: &MatchData,
+    options: &PreviewOptions,
+) -> Result<Preview> {
+    let key = cache_key(data, options);
+    if let Some(preview) = lock(cache).get(&key) {
+        return Ok(preview.clone());
+    }
+
+    // Render without holding the lock so the prefetch thread isn't blocked meanwhile
+    let preview = render_preview(data, options)?;
+    lock(cache).put(key, preview.clone());
+    Ok(preview)
+}
+
+// Function to get the preview of a match only if it was rendered before
+pub fn cached_preview(
+    cache: &PreviewCache,
+    data: &MatchData,
+    options: &PreviewOptions,
+) -> Option<Preview> {
+    lock(cache).get(&cache_key(data, options)).cloned()
+}
+
+// Function to build the cache key of a match. The modification time is taken before
+// rendering, so a file edited meanwhile gets rendered again on the next lookup.
+fn cache_key(data: &MatchData, options: &PreviewOptions) -> CacheKey {
+    let modified = fs::metadata(data.path.os_path())
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    (
+        data.path.text.clone(),
+        data.line_number,
+        modified,
+        options.clone(),
+    )
+}
+
+// Background thread rendering previews of the matches around the selection into the cache,
+// so moving to them shows their preview right away. Requests are handled one at a time on
+// the single thread, which bounds how many `bat` processes run at once.
+pub struct Prefetcher {
+    sender: Sender<Vec<(MatchData, PreviewOptions)>>,
+}
+
+impl Prefetcher {
+    pub fn new(cache: PreviewCache) -> Self {
+        let (sender, receiver) = mpsc::channel::<Vec<(MatchData, PreviewOptions)>>();
+        thread::spawn(move || {
+            while let Ok(mut matches) = receiver.recv() {
+                // Only the neighbours of the latest selection are worth rendering
+                while let Ok(newer) = receiver.try_recv() {
+                    matches = newer;
+                }
+                for (data, options) in matches {
+                    let key = cache_key(&data, &options);
+                    if lock(&cache).contains(&key) {
+                        continue;
+                    }
+                    if let Ok(preview) = render_preview(&data, &options) {
+                        lock(&cache).put(key, preview);
+                    }
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    // Function to queue matches to render, replacing any that haven't been started yet
+    pub fn prefetch(&self, matches: Vec<(MatchData, PreviewOptions)>) {
+        let _ = self.sender.send(matches);
+    }
+}
+
+// Function to render file content around the specific line, showing `options.context` lines
+// on either side. Lines are truncated to `MAX_LINE_LENGTH` unless the preview is soft-wrapped.
+// Uses the configured preview command if there is one, and falls back to reading the file
+// directly if `bat` is missing.
+fn render_preview(data: &MatchData, options: &PreviewOptions) -> Result<Preview> {
+    let file_path = data.path.target_path(options.follow_symlinks)?;
+    let file_path = file_path.as_path();
+    let line_number = data.line_number;
+    let start_line = line_number.saturating_sub(options.context).max(1);
+    // A multiline match gets its context after the last line it spans
+    let end_line = line_number + data.spanned_lines() - 1 + options.context;
+
+    // Huge and binary files would make for a slow or garbled preview
+    let read_error = || format!("Failed to read {}", file_path.display());
+    let size = match fs::metadata(file_path) {
+        Ok(metadata) => metadata.len(),
+        // Saved ripgrep output can outlive the files it points at
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(placeholder(format!("[file not found: {}]", file_path.display())).into());
+        }
+        Err(e) => return Err(e).with_context(read_error),
+    };
+    if size > options.max_file_size {
+        return Ok(placeholder(format!(
+            "[file too large, {:.1} MB]",
+            size as f64 / BYTES_PER_MB as f64
+        ))
+        .into());
+    }
+    let contents = fs::read(file_path).with_context(read_error)?;
+    let lines = line_range(&contents, start_line, end_line);
+    let sniffed = &contents[..contents.len().min(BINARY_SNIFF_LEN)];
+    if sniffed.contains(&0) || lines.iter().any(|line| line.contains(&0)) {
+        return Ok(placeholder("[binary file]".to_string()).into());
+    }
+    let plain_text = || {
+        lines
+            .iter()
+            .map(|line| String::from_utf8_lossy(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    // Changed files in `--git-context` mode come with the file line of each preview line,
+    // since removed lines are shown in between
+    let git_diff = if options.git_context && options.highlighter != Highlighter::None {
+        run_git_diff(file_path, start_line, end_line, options.no_color)
+    } else {
+        None
+    };
+    let mut git_line_map = None;
+    let raw_text = if let Some((diff, lines)) = git_diff {
+        git_line_map = Some(lines);
+        diff
+    } else if options.highlighter == Highlighter::None {
+        plain_text()
+    } else if let Some(command) = &options.preview_command {
+        command.run(file_path, start_line, end_line, line_number)?
+    } else if BAT_MISSING.load(Ordering::Relaxed) {
+        plain_text()
+    } else {
+        match run_bat(file_path, start_line, end_line, options) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                BAT_MISSING.store(true, Ordering::Relaxed);
+                plain_text()
+            }
+            result => result?,
+        }
+    };
+
+    let lines = raw_text.lines().map(strip_carriage_return);
+    let preview_text = if options.wrap {
+        lines.collect::<Vec<_>>().join("\n")
+    } else {
+        // Process each line to truncate it to the max length
+        lines
+            .map(|line| truncate_to_width(&line, MAX_LINE_LENGTH)) // Limit each line length
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let text = preview_text
+        .into_text()
+        .map_err(|e| anyhow::anyhow!("Failed to parse ANSI: {}", e))?;
+    let line_map = git_line_map.unwrap_or_else(|| {
+        (start_line..start_line + text.lines.len())
+            .map(Some)
+            .collect()
+    });
+    let mut preview = Preview { text, line_map };
+
+    // Mark every line the match spans, each with its part of the matched text. Plain input
+    // can claim line 0, which is shown from line 1 like any other match at the top of the
+    // file.
+    let mut segment_start = 0;
+    for offset in 0..data.spanned_lines() {
+        let match_idx = preview.row_of(line_number.max(1) + offset);
+        if let Some(line) = match_idx.and_then(|idx| preview.text.lines.get_mut(idx)) {
+            line.style = line.style.patch(options.match_line_style);
+            // Spans after a color reset, as git emits them, would hide the background
+            for span in &mut line.spans {
+                if span.style.bg == Some(Color::Reset) {
+                    span.style.bg = None;
+                }
+            }
+            highlight_submatches(line, data, segment_start);
+        }
+        segment_start = next_line_start(&data.lines.text, segment_start);
+    }
+    if options.line_numbers {
+        add_line_numbers(&mut preview.text, &preview.line_map);
+    }
+    // Added last, so they stay out of the line numbering and the marked match lines
+    if options.fold_markers {
+        add_fold_markers(&mut preview, start_line, end_line, line_count(&contents));
+    }
+
+    Ok(preview)
+}
+
+// Function to put a line at the top and bottom of the preview saying how many lines of the
+// file come before `start_line` and after `end_line`, where there are any
+fn add_fold_markers(preview: &mut Preview, start_line: usize, end_line: usize, total: usize) {
+    let marker = |count: usize, place: &str| {
+        let noun = if count == 1 { "line" } else { "lines" };
+        Line::styled(format!("⋯ {} {} {}", count, noun, place), FOLD_MARKER_STYLE)
+    };
+    if start_line > 1 {
+        preview
+            .text
+            .lines
+            .insert(0, marker(start_line - 1, "above"));
+        preview.line_map.insert(0, None);
+    }
+    let below = total.saturating_sub(end_line);
+    if below > 0 {
+        preview.text.lines.push(marker(below, "below"));
+        preview.line_map.push(None);
+    }
+}
+
+// Function to prefix each preview line with its right-aligned line number in the file, taken
+// from `line_map`. Lines without one, such as removed lines of a diff, get a blank gutter.
+// The gutter is added after parsing so neither the highlighter's ANSI output nor the
+// submatch offsets have to account for it.
+fn add_line_numbers(text: &mut Text<'static>, line_map: &[Option<usize>]) {
+    let last_line = line_map.iter().flatten().max().copied().unwrap_or(0);
+    let width = last_line.to_string().len();
+    for (line, number) in text.lines.iter_mut().zip(line_map) {
+        let gutter = match number {
+            Some(number) => format!("{:>width$} │ ", number, width = width),
+            None => format!("{:width$} │ ", "", width = width),
+        };
+        line.spans
+            .insert(0, Span::styled(gutter, LINE_NUMBER_STYLE));
+    }
+}
+
+// Function to set `style` on the preview lines ripgrep reported as context of the match,
+// given their line numbers in the file. The match line keeps its own marking.
+pub fn mark_context_lines(
+    preview: &mut Preview,
+    line_number: usize,
+    context_lines: &HashSet<usize>,
+    style: Style,
+) {
+    for (line, number) in preview.text.lines.iter_mut().zip(&preview.line_map) {
+        match number {
+            Some(number) if *number != line_number && context_lines.contains(number) => {
+                line.style = line.style.patch(style);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Function to mark the exact matched text on a line of the match, the one starting at byte
+// `segment_start` of `lines.text`. Submatch offsets are bytes into `lines.text`, so they are
+// clipped to that line and converted to character positions before being applied to the
+// rendered spans. Ranges cut off by truncation are simply clipped.
+fn highlight_submatches(line: &mut Line<'static>, data: &MatchData, segment_start: usize) {
+    let text = &data.lines.text;
+    let segment_end = next_line_start(text, segment_start);
+    let char_ranges: Vec<(usize, usize)> = data
+        .submatches
+        .iter()
+        .filter_map(|submatch| {
+            let start = submatch.start.clamp(segment_start, segment_end);
+            let end = submatch.end.clamp(segment_start, segment_end);
+            let start = text.get(segment_start..start)?.chars().count();
+            let end = text.get(segment_start..end)?.chars().count();
+            Some((start, end))
+        })
+        .collect();
+    let reversed = Style::new().add_modifier(Modifier::REVERSED);
+    highlight_char_ranges(line, &char_ranges, reversed, 0);
+}
+
+// Function to get the byte offset where the line after the one starting at `start` begins,
+// or the end of `text`
+fn next_line_start(text: &str, start: usize) -> usize {
+    text.get(start..)
+        .and_then(|rest| rest.find('\n'))
+        .map_or(text.len(), |newline| start + newline + 1)
+}
+
+// Function to patch `highlight` onto the given character ranges of a line, splitting its
+// spans where a range starts or ends. The first `skip` spans, such as the line-number
+// gutter, are left alone and not counted.
+fn highlight_char_ranges(
+    line: &mut Line<'static>,
+    char_ranges: &[(usize, usize)],
+    highlight: Style,
+    skip: usize,
+) {
+    if char_ranges.is_empty() {
+        return;
+    }
+
+    let in_match = |idx: usize| {
+        char_ranges
+            .iter()
+            .any(|&(start, end)| idx >= start && idx < end)
+    };
+    let styled = |content: String, style: Style, matched: bool| {
+        if matched {
+            Span::styled(content, style.patch(highlight))
+        } else {
+            Span::styled(content, style)
+        }
+    };
+    let mut spans: Vec<Span<'static>> = line.spans.drain(..skip.min(line.spans.len())).collect();
+    let mut idx = 0;
+    for span in line.spans.drain(..) {
+        // Split each span into runs that are entirely inside or outside a range
+        let mut run = String::new();
+        let mut run_matched = in_match(idx);
+        for c in span.content.chars() {
+            if in_match(idx) != run_matched {
+                spans.push(styled(std::mem::take(&mut run), span.style, run_matched));
+                run_matched = !run_matched;
+            }
+            run.push(c);
+            idx += 1;
+        }
+        if !run.is_empty() {
+            spans.push(styled(run, span.style, run_matched));
+        }
+    }
+    line.spans = spans;
+}
+
+// Function to highlight every occurrence of `query` in the preview, case-insensitively
+// unless it has uppercase characters, and return the indices of the lines it occurs on.
+// With `line_numbers` the gutter span of each line isn't searched.
+pub fn find_in_preview(text: &mut Text<'static>, query: &str, line_numbers: bool) -> Vec<usize> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |s: &str| -> Vec<char> {
+        if ignore_case {
+            s.chars().flat_map(char::to_lowercase).collect()
+        } else {
+            s.chars().collect()
+        }
+    };
+    let needle = fold(query);
+    let skip = usize::from(line_numbers);
+
+    let mut hits = Vec::new();
+    for (line_idx, line) in text.lines.iter_mut().enumerate() {
+        let content: String = line
+            .spans
+            .iter()
+            .skip(skip)
+            .map(|span| &*span.content)
+            .collect();
+        // Ranges are character positions, which lowercasing shifts for the rare characters
+        // that lowercase to several; such lines aren't searched
+        let haystack = fold(&content);
+        if haystack.len() != content.chars().count() {
+            continue;
+        }
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while start + needle.len() <= haystack.len() {
+            if haystack[start..start + needle.len()] == needle[..] {
+                ranges.push((start, start + needle.len()));
+                start += needle.len();
+            } else {
+                start += 1;
+            }
+        }
+        if !ranges.is_empty() {
+            highlight_char_ranges(line, &ranges, PREVIEW_SEARCH_STYLE, skip);
+            hits.push(line_idx);
+        }
+    }
+    hits
+}
+
+// Function to render the given line range with `bat`. A missing `bat` binary is reported as
+// `io::ErrorKind::NotFound` so the caller can fall back to plain text.
+fn run_bat(
+    file_path: &Path,
+    start_line: usize,
+    end_line: usize,
+    options: &PreviewOptions,
+) -> io::Result<String> {
+    // Use `bat` with color enabled, unless colors are turned off
+    let color = if options.no_color { "never" } else { "always" };
+    let mut command = Command::new("bat");
+    if let Some(theme) = &options.bat_theme {
+        command.args(["--theme", theme]);
+    }
+    let output = command
+        .args([
+            "--style",
+            "plain",
+            "--paging",
+            "never",
+            "--color",
+            color, // Enable color for ANSI escape sequences
+            "--line-range",
+            &format!("{}:{}", start_line, end_line), // Context range around the match
+        ])
+        .arg(file_path)
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(io::Error::other(format!(
+            "Error running bat ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )))
+    }
+}
+
+// Function to render the preview from `git diff` against HEAD with the whole file as
+// context, so lines added since HEAD show in git's colors and removed ones in between. The
+// `+`/`-` markers are dropped to keep the text aligned with the file; each preview line
+// comes with its line number in the file, or `None` for a removed line. Returns `None` when
+// git has no diff to show, e.g. outside a repository or for an untracked or unchanged file.
+fn run_git_diff(
+    file_path: &Path,
+    start_line: usize,
+    end_line: usize,
+    no_color: bool,
+) -> Option<(String, Vec<Option<usize>>)> {
+    let dir = file_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let color = if no_color {
+        "--color=never"
+    } else {
+        "--color=always"
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["diff", "--no-ext-diff", color])
+        .arg(format!("--unified={}", WHOLE_FILE_CONTEXT))
+        .args(["HEAD", "--"])
+        .arg(file_path.file_name()?)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let diff = String::from_utf8_lossy(&output.stdout);
+    // Everything up to the single hunk header is the file header
+    let mut diff_lines = diff
+        .lines()
+        .skip_while(|line| !skip_escapes(line).starts_with("@@"));
+    diff_lines.next()?;
+
+    let mut text = Vec::new();
+    let mut line_map = Vec::new();
+    // File line the next context or added line has
+    let mut next_line = 1;
+    for diff_line in diff_lines {
+        let visible = skip_escapes(diff_line);
+        let number = match visible.chars().next() {
+            Some('-') => None,
+            Some('+' | ' ') => {
+                next_line += 1;
+                Some(next_line - 1)
+            }
+            // `\ No newline at end of file`
+            _ => continue,
+        };
+        // Removed lines show where they were, between the shown lines around them
+        let shown = match number {
+            Some(number) => (start_line..=end_line).contains(&number),
+            None => next_line > start_line && next_line <= end_line,
+        };
+        if shown {
+            let escapes = &diff_line[..diff_line.len() - visible.len()];
+            text.push(format!("{}{}", escapes, &visible[1..]));
+            line_map.push(number);
+        }
+    }
+    Some((text.join("\n"), line_map))
+}
+
+// Function to skip the ANSI escape sequences at the start of `s`
+fn skip_escapes(mut s: &str) -> &str {
+    loop {
+        match ansi_escape_len(s) {
+            0 => return s,
+            len => s = &s[len..],
+        }
+    }
+}
+
+// Function to drop the `\r` a Windows line ending leaves at the end of a line, also when the
+// highlighter put escape sequences after it
+fn strip_carriage_return(line: &str) -> Cow<'_, str> {
+    match line.rfind('\r') {
+        Some(pos) if skip_escapes(&line[pos + 1..]).is_empty() => {
+            Cow::Owned(format!("{}{}", &line[..pos], &line[pos + 1..]))
+        }
+        _ => Cow::Borrowed(line),
+    }
+}
+
+// Function to get the given (1-based, inclusive) line range of the file contents, without
+// line endings
+fn line_range(contents: &[u8], start_line: usize, end_line: usize) -> Vec<&[u8]> {
+    contents
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .skip(start_line - 1)
+        .take(end_line + 1 - start_line)
+        .collect()
+}
+
+// Function to count the lines of the file contents, where a final line ending doesn't start
+// another line
+fn line_count(contents: &[u8]) -> usize {
+    let newlines = contents.iter().filter(|&&b| b == b'\n').count();
+    newlines + usize::from(!contents.is_empty() && !contents.ends_with(b"\n"))
+}
+
+// Function to show a note in place of the file contents
+fn placeholder(note: String) -> Text<'static> {
+    Text::styled(note, PLACEHOLDER_STYLE)
+}
+
+// Function to truncate a line to `max_width` display columns without splitting a grapheme
+// or an ANSI escape sequence. Escape sequences past the cut are kept so styles still reset.
+fn truncate_to_width(line: &str, max_width: usize) -> String {
+    let mut truncated = String::with_capacity(line.len());
+    let mut width = 0;
+    let mut cut = false;
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        let escape_len = ansi_escape_len(rest);
+        if escape_len > 0 {
+            truncated.push_str(&rest[..escape_len]);
+            rest = &rest[escape_len..];
+            continue;
+        }
+
+        // Handle the plain text up to the next escape sequence grapheme by grapheme
+        let text_len = rest.find('\x1b').filter(|&i| i > 0).unwrap_or(rest.len());
+        if !cut {
+            for grapheme in rest[..text_len].graphemes(true) {
+                let grapheme_width = grapheme.width();
+                if width + grapheme_width > max_width {
+                    cut = true;
+                    break;
+                }
+                width += grapheme_width;
+                truncated.push_str(grapheme);
+            }
+        }
+        rest = &rest[text_len..];
+    }
+
+    truncated
+}
+
+// Function to get the byte length of the ANSI escape sequence at the start of `s`, or 0
+fn ansi_escape_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    if bytes.first() != Some(&0x1b) {
+        return 0;
+    }
+
+    match bytes.get(1) {
+        // CSI: ESC [ parameters... final byte in 0x40..=0x7e
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+            .map_or(bytes.len(), |i| i + 3),
+        // OSC: ESC ] ... terminated by BEL or ESC \
+        Some(b']') => {
+            let mut i = 2;
+            while i < bytes.len() {
+                match bytes[i] {
+                    0x07 => return i + 1,
+                    0x1b if bytes.get(i + 1) == Some(&b'\\') => return i + 2,
+                    _ => i += 1,
+                }
+            }
+            bytes.len()
+        }
+        // Two-byte escape; keep the following character whole
+        Some(_) => 1 + s[1..].chars().next().map_or(0, char::len_utf8),
+        None => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Function to write `contents` to a file of its own in the temp directory
+    fn temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("rgnav-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn match_at(path: &Path, line_number: usize, text: &str) -> MatchData {
+        serde_json::from_value(serde_json::json!({
+            "path": { "text": path.to_str().unwrap() },
+            "lines": { "text": text },
+            "line_number": line_number,
+            "submatches": [],
+        }))
+        .unwrap()
+    }
+
+    fn plain_options() -> PreviewOptions {
+        PreviewOptions {
+            context: 2,
+            wrap: false,
+            highlighter: Highlighter::None,
+            max_file_size: BYTES_PER_MB,
+            line_numbers: false,
+            fold_markers: true,
+            match_line_style: Style::default(),
+            bat_theme: None,
+            preview_command: None,
+            no_color: true,
+            git_context: false,
+            follow_symlinks: false,
+        }
+    }
+
+    // Function to check that `truncated` is `line` cut after a whole grapheme and fits in
+    // `MAX_LINE_LENGTH` columns
+    fn assert_cut_cleanly(line: &str, truncated: &str) {
+        assert!(
+            truncated.width() <= MAX_LINE_LENGTH,
+            "too wide: {}",
+            truncated
+        );
+        let kept: Vec<&str> = truncated.graphemes(true).collect();
+        let original: Vec<&str> = line.graphemes(true).take(kept.len()).collect();
+        assert_eq!(kept, original);
+    }
+
+    #[test]
+    fn truncates_wide_characters_without_splitting_them() {
+        // One narrow character first, so a double-width one straddles column 80
+        let cjk = format!("a{}", "漢字".repeat(50));
+        let emoji = format!("a{}", "👍🏽🎉".repeat(30));
+        let family = "👨‍👩‍👧‍👦".repeat(60);
+        for line in [&cjk, &emoji, &family] {
+            assert!(line.width() > MAX_LINE_LENGTH);
+            let truncated = truncate_to_width(line, MAX_LINE_LENGTH);
+            assert_cut_cleanly(line, &truncated);
+            assert!(truncated.width() >= MAX_LINE_LENGTH - 1);
+        }
+    }
+
+    #[test]
+    fn truncation_keeps_escape_sequences_out_of_the_width() {
+        let line = format!("\x1b[31m{}\x1b[0m", "漢".repeat(60));
+        let truncated = truncate_to_width(&line, MAX_LINE_LENGTH);
+        assert!(truncated.ends_with("\x1b[0m"));
+        let plain = truncated
+            .trim_start_matches("\x1b[31m")
+            .trim_end_matches("\x1b[0m");
+        assert_eq!(plain, "漢".repeat(40));
+    }
+
+    #[test]
+    fn line_zero_previews_from_the_top() {
+        let path = temp_file("line-zero", "first\nsecond\nthird\nfourth\n");
+        let preview = render_preview(&match_at(&path, 0, "first"), &plain_options()).unwrap();
+        let lines: Vec<String> = preview
+            .text
+            .lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(lines, ["first", "second", "⋯ 2 lines below"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn context_lines_follow_the_line_map() {
+        // As `--git-context` shows a line removed right above the match
+        let mut preview = Preview {
+            text: Text::from("four\nremoved\nfive\nsix"),
+            line_map: vec![Some(4), None, Some(5), Some(6)],
+        };
+        let style = Style::new().fg(Color::Cyan);
+        mark_context_lines(&mut preview, 5, &HashSet::from([4, 6]), style);
+        let marked: Vec<bool> = preview
+            .text
+            .lines
+            .iter()
+            .map(|line| line.style == style)
+            .collect();
+        assert_eq!(marked, [true, false, false, true]);
+        assert_eq!(preview.row_of(5), Some(2));
+    }
+
+    #[test]
+    fn fold_markers_are_left_out_of_the_line_map() {
+        let path = temp_file("line-map", "1\n2\n3\n4\n5\n6\n7\n8\n");
+        let preview = render_preview(&match_at(&path, 5, "5"), &plain_options()).unwrap();
+        assert_eq!(
+            preview.line_map,
+            [None, Some(3), Some(4), Some(5), Some(6), Some(7), None]
+        );
+        assert_eq!(preview.row_of(5), Some(3));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,46 +1,699 @@
+use crate::base64;
+use crate::events::Waker;
 use anyhow::{Context, Result};
 use atty::Stream;
 use serde::Deserialize;
-use std::io::{self, BufRead};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
 
-#[derive(Debug, Deserialize)]
+// Options controlling how the readers parse their input
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    // Number of match records read from each source at most, from `--max-matches`
+    pub max_matches: Option<usize>,
+    // Separator between the path, line number and text of plain input lines, from
+    // `--field-separator`; `:` when unset
+    pub field_separator: Option<String>,
+}
+
+#[derive(Debug)]
 pub struct RgMatch {
     pub data: Option<MatchData>,
 }
 
+// A single record of `rg --json` output, told apart by its `type` field
 #[derive(Debug, Deserialize)]
-pub struct MatchData {
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record {
+    Match {
+        data: MatchData,
+    },
+    Summary {
+        data: Summary,
+    },
+    Context {
+        data: ContextData,
+    },
+    // `begin` and `end` records carry nothing we show
+    #[serde(other)]
+    Other,
+}
+
+// Something read from ripgrep output that the UI cares about
+#[derive(Debug)]
+pub enum Parsed {
+    Match(RgMatch),
+    Summary(Summary),
+    Context(ContextData),
+    // `max_matches` was reached and further matches are dropped
+    Truncated,
+}
+
+// A line around a match that ripgrep reported for `-A`, `-B` or `-C`
+#[derive(Debug, Deserialize)]
+pub struct ContextData {
     pub path: PathInfo,
     pub line_number: usize,
 }
 
+// The final `summary` record of `rg --json` output
 #[derive(Debug, Deserialize)]
+pub struct Summary {
+    pub stats: Stats,
+}
+
+// Totals for the whole search, from the `stats` of the summary record
+#[derive(Debug, Deserialize)]
+pub struct Stats {
+    pub elapsed: Elapsed,
+    pub searches_with_match: u64,
+    pub matches: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Elapsed {
+    pub secs: u64,
+    pub nanos: u32,
+}
+
+impl Stats {
+    // Function to describe the search like "3 matches in 2 files, searched in 5ms"
+    pub fn describe(&self) -> String {
+        let millis = self.elapsed.secs * 1000 + u64::from(self.elapsed.nanos) / 1_000_000;
+        format!(
+            "{} matches in {} files, searched in {}ms",
+            self.matches, self.searches_with_match, millis
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatchData {
+    pub path: PathInfo,
+    #[serde(default)]
+    pub lines: LineInfo,
+    pub line_number: usize,
+    // Byte offset of the start of the matched line in the file, missing from plain input
+    #[serde(default)]
+    pub absolute_offset: Option<u64>,
+    #[serde(default)]
+    pub submatches: Vec<SubMatch>,
+    // Number of lines the match spans, if the producer reports it; see `spanned_lines`
+    #[serde(default)]
+    pub line_count: Option<usize>,
+}
+
+// Path of a matched file. ripgrep reports paths that aren't valid UTF-8 as base64 `bytes`
+// instead of `text`; those keep their raw bytes for opening the file and a lossy `text` for
+// display.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawPathInfo")]
 pub struct PathInfo {
     pub text: String,
+    bytes: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct LineInfo {}
+#[derive(Deserialize)]
+struct RawPathInfo {
+    text: Option<String>,
+    bytes: Option<String>,
+}
+
+impl TryFrom<RawPathInfo> for PathInfo {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawPathInfo) -> Result<Self> {
+        match (raw.text, raw.bytes) {
+            (Some(text), _) => Ok(Self { text, bytes: None }),
+            (None, Some(encoded)) => {
+                let bytes = base64::decode(&encoded)?;
+                Ok(Self {
+                    text: String::from_utf8_lossy(&bytes).into_owned(),
+                    bytes: Some(bytes),
+                })
+            }
+            (None, None) => Err(anyhow::anyhow!("Path has neither `text` nor `bytes`")),
+        }
+    }
+}
+
+impl MatchData {
+    // Function to get the 1-based column of the first submatch, counted in characters of the
+    // matched line rather than bytes. Plain `path:line:text` input has no submatches.
+    pub fn column(&self) -> Option<usize> {
+        let submatch = self.submatches.first()?;
+        let before = self.lines.text.get(..submatch.start)?;
+        Some(before.chars().count() + 1)
+    }
+
+    // Function to get the 1-based column of the first submatch in bytes, which is what vim's
+    // `cursor()` expects
+    pub fn byte_column(&self) -> Option<usize> {
+        let submatch = self.submatches.first()?;
+        self.lines.text.get(..submatch.start)?;
+        Some(submatch.start + 1)
+    }
+
+    // Function to get how many lines the match spans. Multiline matches (`rg -U`) report the
+    // first line's number but carry every spanned line in `lines.text`.
+    pub fn spanned_lines(&self) -> usize {
+        self.line_count
+            .unwrap_or_else(|| {
+                self.lines
+                    .text
+                    .trim_end_matches(['\n', '\r'])
+                    .lines()
+                    .count()
+            })
+            .max(1)
+    }
+
+    // Function to format the match's position as `line:col`, or just the line without a
+    // column
+    pub fn location(&self) -> String {
+        match self.column() {
+            Some(column) => format!("{}:{}", self.line_number, column),
+            None => self.line_number.to_string(),
+        }
+    }
+}
+
+impl PathInfo {
+    // Function to get the path as the OS sees it, which may differ from the displayed text
+    pub fn os_path(&self) -> PathBuf {
+        match &self.bytes {
+            #[cfg(unix)]
+            Some(bytes) => {
+                use std::os::unix::ffi::OsStrExt;
+                PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+            }
+            _ => PathBuf::from(&self.text),
+        }
+    }
+
+    // Function to get the path to preview and open, resolved through symlinks with
+    // `--follow-symlinks` (`follow`), so previews and editors get the file a symlinked path
+    // points at while the list keeps showing the link. Only a dangling link is an error;
+    // other failures leave the path as it is for whatever reads it to report.
+    pub fn target_path(&self, follow: bool) -> Result<PathBuf> {
+        let path = self.os_path();
+        if !follow {
+            return Ok(path);
+        }
+        match fs::canonicalize(&path) {
+            Ok(resolved) => Ok(resolved),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && path.is_symlink() => {
+                let target = fs::read_link(&path)
+                    .map(|target| target.display().to_string())
+                    .unwrap_or_default();
+                Err(anyhow::anyhow!(
+                    "Broken symlink {} -> {}",
+                    path.display(),
+                    target
+                ))
+            }
+            Err(_) => Ok(path),
+        }
+    }
+}
+
+// Byte range of a single match within `lines.text`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubMatch {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LineInfo {
+    #[serde(default)]
+    pub text: String,
+}
+
+// Matches being read on a background thread, from piped input or from an `rg` process
+// started for `--search`. Dropping the source kills that process.
+pub(crate) struct MatchSource {
+    receiver: Receiver<Result<Parsed>>,
+    child: Option<Child>,
+}
+
+impl MatchSource {
+    // Function to take the next parsed record if one is ready. Disconnects once the input
+    // is exhausted.
+    pub fn try_recv(&self) -> Result<Result<Parsed>, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    // Function to wait for the next parsed record, or `None` once the input is exhausted
+    pub fn recv(&self) -> Option<Result<Parsed>> {
+        self.receiver.recv().ok()
+    }
+}
+
+// Sending half of a `MatchSource`, which wakes the main loop for every record and once more
+// when the reader thread finishes
+struct RecordSender {
+    sender: Option<Sender<Result<Parsed>>>,
+    waker: Waker,
+}
+
+impl RecordSender {
+    fn new(waker: Waker) -> (Self, Receiver<Result<Parsed>>) {
+        let (sender, receiver) = mpsc::channel();
+        let sender = Self {
+            sender: Some(sender),
+            waker,
+        };
+        (sender, receiver)
+    }
 
-// Function to read ripgrep output from stdin
-pub fn get_rg_matches() -> Result<Vec<RgMatch>> {
-    // Exit immediately if `stdin` is a terminal (not piped)
-    if atty::is(Stream::Stdin) {
-        return Err(anyhow::anyhow!(
-            "No piped input detected. Please pipe `rg` output to `rgnav`."
-        ));
+    // Function to pass a record on to the UI, returning false once nobody is receiving
+    fn send(&self, record: Result<Parsed>) -> bool {
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(record).is_ok());
+        self.waker.wake();
+        sent
     }
+}
+
+impl Drop for RecordSender {
+    fn drop(&mut self) {
+        // Disconnect first so the woken loop sees that the input is exhausted
+        self.sender = None;
+        self.waker.wake();
+    }
+}
+
+impl Drop for MatchSource {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// Function to start reading ripgrep output from `input`, or from stdin when no file is
+// given. Opening and parsing happen on a background thread so the UI comes up right away,
+// even for a named pipe whose writer hasn't started yet (opening a FIFO blocks until then).
+pub(crate) fn get_rg_matches(
+    input: Option<&str>,
+    options: ParseOptions,
+    waker: Waker,
+) -> Result<MatchSource> {
+    match input {
+        // Catch a mistyped path before the UI starts
+        Some(path) => {
+            fs::metadata(path).with_context(|| format!("Failed to open {}", path))?;
+        }
+        // Exit immediately if `stdin` is a terminal (not piped)
+        None if atty::is(Stream::Stdin) => {
+            return Err(anyhow::anyhow!(
+                "No piped input detected. Please pipe `rg` output to `rgnav` or pass a file."
+            ));
+        }
+        None => {}
+    }
+
+    let input = input.map(str::to_string);
+    let (sender, receiver) = RecordSender::new(waker);
+    thread::spawn(move || {
+        let result = match input {
+            Some(path) => File::open(&path)
+                .with_context(|| format!("Failed to open {}", path))
+                .and_then(|file| parse_rg_output(BufReader::new(file), &options, &sender)),
+            None => parse_rg_output(io::stdin().lock(), &options, &sender),
+        };
+        if let Err(e) = result {
+            let _ = sender.send(Err(e));
+        }
+    });
+
+    Ok(MatchSource {
+        receiver,
+        child: None,
+    })
+}
+
+// Function to run `rg --json` for `query` in `dir` and read its matches in the background.
+// Errors rg reports, such as an invalid regex, arrive once its output is exhausted.
+pub(crate) fn spawn_rg_search(
+    query: &str,
+    dir: &str,
+    options: ParseOptions,
+    waker: Waker,
+) -> Result<MatchSource> {
+    let mut command = Command::new("rg");
+    command.args(["--json", "--no-messages", "--", query, dir]);
+    read_command_output(command, options, waker).context("Failed to run `rg`")
+}
+
+// Function to run a shell command line given with `--rg-cmd`, such as `rg --json TODO src`,
+// and read its output like piped input
+pub(crate) fn spawn_rg_command(
+    command_line: &str,
+    options: ParseOptions,
+    waker: Waker,
+) -> Result<MatchSource> {
+    let mut command = Command::new("sh");
+    command.args(["-c", command_line]);
+    read_command_output(command, options, waker)
+        .with_context(|| format!("Failed to run `{}`", command_line))
+}
+
+// Function to start `command` and parse its stdout on a background thread. The first line
+// it writes to stderr is reported as an error once stdout ends.
+fn read_command_output(
+    mut command: Command,
+    options: ParseOptions,
+    waker: Waker,
+) -> Result<MatchSource> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().context("stdout is not captured")?;
+    let mut stderr = child.stderr.take().context("stderr is not captured")?;
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let (sender, receiver) = RecordSender::new(waker);
+    thread::spawn(move || {
+        let mut result = parse_rg_output(BufReader::new(stdout), &options, &sender);
+        let mut errors = String::new();
+        if result.is_ok() && stderr.read_to_string(&mut errors).is_ok() {
+            if let Some(error) = errors.lines().find(|line| !line.trim().is_empty()) {
+                result = Err(anyhow::anyhow!("{}: {}", program, error.trim()));
+            }
+        }
+        if let Err(e) = result {
+            let _ = sender.send(Err(e));
+        }
+    });
+
+    Ok(MatchSource {
+        receiver,
+        child: Some(child),
+    })
+}
+
+// Function to parse ripgrep output, either `--json` records or plain `path:line:text` lines,
+// sending each match as soon as it's parsed. The format is picked from the first non-empty
+// line so that the whole stream is parsed consistently; of the JSON records only `match`,
+// `context` and `summary` are kept.
+fn parse_rg_output(
+    mut reader: impl BufRead,
+    options: &ParseOptions,
+    sender: &RecordSender,
+) -> Result<()> {
+    let max_matches = options.max_matches;
+    let separator = options.field_separator.as_deref().unwrap_or(":");
+    let mut matches = 0;
+    let mut truncated = false;
+    let mut plain = None;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = reader
+            .read_until(b'\n', &mut buf)
+            .context("Failed to read line from ripgrep output")?;
+        if read == 0 {
+            break;
+        }
+        // Plain output carries the matched lines' raw bytes, which needn't be UTF-8; a bad
+        // byte shouldn't cost the line, let alone the rest of the input
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let plain =
+            *plain.get_or_insert_with(|| serde_json::from_str::<serde_json::Value>(line).is_err());
+        let parsed = if plain {
+            parse_plain_line(line, separator).map(Parsed::Match)
+        } else {
+            match serde_json::from_str::<Record>(line) {
+                Ok(Record::Match { data }) => Some(Parsed::Match(RgMatch { data: Some(data) })),
+                Ok(Record::Summary { data }) => Some(Parsed::Summary(data)),
+                Ok(Record::Context { data }) => Some(Parsed::Context(data)),
+                _ => None,
+            }
+        };
+        // Past the limit keep reading, so the writer doesn't die of a broken pipe, but drop
+        // the matches
+        let parsed = match parsed {
+            Some(Parsed::Match(_)) if max_matches.is_some_and(|max| matches >= max) => (!truncated)
+                .then(|| {
+                    truncated = true;
+                    Parsed::Truncated
+                }),
+            Some(Parsed::Match(rg_match)) => {
+                matches += 1;
+                Some(Parsed::Match(rg_match))
+            }
+            other => other,
+        };
+        if let Some(parsed) = parsed {
+            // Stop reading once the UI has gone away
+            if !sender.send(Ok(parsed)) {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Function to parse a grep-style `path:line_number:content` line, with `separator` in place
+// of the colons. Paths may contain the separator themselves, so the path ends at the first
+// separator that is followed by a line number and another separator.
+fn parse_plain_line(line: &str, separator: &str) -> Option<RgMatch> {
+    // Reading lines only takes off one `\r\n`, a match from a CRLF file can bring its own `\r`
+    let line = line.trim_end_matches('\r');
+    // Don't mistake the colon of a Windows drive letter (`C:\src\main.rs`) for a separator
+    let bytes = line.as_bytes();
+    let path_start = match bytes {
+        [drive, b':', b'\\' | b'/', ..] if drive.is_ascii_alphabetic() => 2,
+        _ => 0,
+    };
 
-    let stdin = io::stdin();
-    let reader = stdin.lock();
+    let (path_end, line_number, content) =
+        line[path_start..]
+            .match_indices(separator)
+            .find_map(|(offset, _)| {
+                let path_end = path_start + offset;
+                let (line_number, content) =
+                    line[path_end + separator.len()..].split_once(separator)?;
+                let line_number = line_number.parse().ok()?;
+                Some((path_end, line_number, content))
+            })?;
 
-    let mut matches = Vec::new();
-    for line in reader.lines() {
-        let line = line.context("Failed to read line from ripgrep output")?;
-        if let Ok(rg_match) = serde_json::from_str::<RgMatch>(&line) {
-            matches.push(rg_match);
+    Some(RgMatch {
+        data: Some(MatchData {
+            path: PathInfo {
+                text: line[..path_end].to_string(),
+                bytes: None,
+            },
+            lines: LineInfo {
+                text: content.to_string(),
+            },
+            line_number,
+            absolute_offset: None,
+            submatches: Vec::new(),
+            line_count: None,
+        }),
+    })
+}
+
+// All matches within a single file
+#[derive(Debug)]
+pub struct FileGroup {
+    pub line_numbers: Vec<usize>,
+}
+
+// Matches aggregated by file path, in order of first appearance
+#[derive(Debug, Default)]
+pub struct GroupedMatches {
+    pub groups: Vec<FileGroup>,
+    index: HashMap<String, usize>,
+}
+
+impl GroupedMatches {
+    // Function to add a match to the group for its file, returning which match in the file
+    // it is (1-based)
+    pub fn add(&mut self, data: &MatchData) -> usize {
+        let idx = *self.index.entry(data.path.text.clone()).or_insert_with(|| {
+            self.groups.push(FileGroup {
+                line_numbers: Vec::new(),
+            });
+            self.groups.len() - 1
+        });
+        self.groups[idx].line_numbers.push(data.line_number);
+        self.groups[idx].line_numbers.len()
+    }
+
+    // Function to count the matches in a file
+    pub fn count(&self, path: &str) -> usize {
+        self.index
+            .get(path)
+            .map_or(0, |&idx| self.groups[idx].line_numbers.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_keeps_every_submatch() {
+        let line = r#"{"type":"match","data":{"path":{"text":"src/main.rs"},"lines":{"text":"let foo = foo(foo);\n"},"line_number":3,"absolute_offset":42,"submatches":[{"match":{"text":"foo"},"start":4,"end":7},{"match":{"text":"foo"},"start":10,"end":13},{"match":{"text":"foo"},"start":14,"end":17}]}}"#;
+        let data = match serde_json::from_str::<Record>(line).unwrap() {
+            Record::Match { data } => data,
+            other => panic!("expected a match, got {:?}", other),
+        };
+        let spans: Vec<(usize, usize)> = data
+            .submatches
+            .iter()
+            .map(|submatch| (submatch.start, submatch.end))
+            .collect();
+        assert_eq!(spans, [(4, 7), (10, 13), (14, 17)]);
+        for (start, end) in spans {
+            assert_eq!(&data.lines.text[start..end], "foo");
         }
     }
 
-    Ok(matches)
+    #[test]
+    fn summary_describes_the_search() {
+        // As printed by `rg --json` 14
+        let line = r#"{"data":{"elapsed_total":{"human":"0.009515s","nanos":9515438,"secs":0},"stats":{"bytes_printed":3168,"bytes_searched":48609,"elapsed":{"human":"0.012345s","nanos":12345678,"secs":1},"matches":12,"matched_lines":11,"searches":9,"searches_with_match":4}},"type":"summary"}"#;
+        let summary = match serde_json::from_str::<Record>(line).unwrap() {
+            Record::Summary { data } => data,
+            other => panic!("expected a summary, got {:?}", other),
+        };
+        assert_eq!(
+            summary.stats.describe(),
+            "12 matches in 4 files, searched in 1012ms"
+        );
+    }
+
+    // Reader handing out whatever chunks a test feeds it, a few bytes per read, like a pipe
+    // from a slow producer. Ends once the feeding side is dropped.
+    struct ChunkedReader {
+        chunks: Receiver<Vec<u8>>,
+        pending: Vec<u8>,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pending.is_empty() {
+                match self.chunks.recv() {
+                    Ok(chunk) => self.pending = chunk,
+                    Err(_) => return Ok(0),
+                }
+            }
+            let len = buf.len().min(self.pending.len()).min(5);
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    fn match_text(record: Option<Result<Parsed>>) -> String {
+        match record {
+            Some(Ok(Parsed::Match(RgMatch { data: Some(data) }))) => data.lines.text,
+            other => panic!("expected a match, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn matches_arrive_as_their_lines_complete() {
+        let (feed, chunks) = mpsc::channel();
+        let (sender, receiver) = RecordSender::new(Waker::detached());
+        let reader = thread::spawn(move || {
+            let reader = ChunkedReader {
+                chunks,
+                pending: Vec::new(),
+            };
+            parse_rg_output(BufReader::new(reader), &ParseOptions::default(), &sender)
+        });
+        let next = || {
+            receiver
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .ok()
+        };
+
+        let first = r#"{"type":"match","data":{"path":{"text":"a.rs"},"lines":{"text":"first\n"},"line_number":1}}"#;
+        let second = r#"{"type":"match","data":{"path":{"text":"b.rs"},"lines":{"text":"second, split across reads\n"},"line_number":2}}"#;
+        let (head, tail) = second.split_at(40);
+        feed.send(format!("{}\n{}", first, head).into_bytes())
+            .unwrap();
+        // The first match is out while the second line is still being written
+        assert_eq!(match_text(next()), "first\n");
+        assert!(receiver.try_recv().is_err());
+
+        feed.send(tail.as_bytes().to_vec()).unwrap();
+        feed.send(b"\n".to_vec()).unwrap();
+        assert_eq!(match_text(next()), "second, split across reads\n");
+
+        drop(feed);
+        reader.join().unwrap().unwrap();
+        assert!(next().is_none());
+    }
+
+    #[test]
+    fn context_records_keep_their_place_around_matches() {
+        // `rg --json -C1 needle` over a three-line file
+        let output = [
+            r#"{"type":"begin","data":{"path":{"text":"notes.txt"}}}"#,
+            r#"{"type":"context","data":{"path":{"text":"notes.txt"},"lines":{"text":"hay\n"},"line_number":1,"absolute_offset":0,"submatches":[]}}"#,
+            r#"{"type":"match","data":{"path":{"text":"notes.txt"},"lines":{"text":"needle\n"},"line_number":2,"absolute_offset":4,"submatches":[{"match":{"text":"needle"},"start":0,"end":6}]}}"#,
+            r#"{"type":"context","data":{"path":{"text":"notes.txt"},"lines":{"text":"stack\n"},"line_number":3,"absolute_offset":11,"submatches":[]}}"#,
+            r#"{"type":"end","data":{"path":{"text":"notes.txt"},"binary_offset":null,"stats":{"matches":1}}}"#,
+        ]
+        .join("\n");
+        let (sender, receiver) = RecordSender::new(Waker::detached());
+        parse_rg_output(output.as_bytes(), &ParseOptions::default(), &sender).unwrap();
+        drop(sender);
+
+        let records: Vec<String> = receiver
+            .iter()
+            .map(|record| match record.unwrap() {
+                Parsed::Context(data) => format!("context {}:{}", data.path.text, data.line_number),
+                Parsed::Match(RgMatch { data: Some(data) }) => {
+                    format!("match {}:{}", data.path.text, data.line_number)
+                }
+                other => panic!("unexpected record {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            records,
+            [
+                "context notes.txt:1",
+                "match notes.txt:2",
+                "context notes.txt:3"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_costs_only_its_own_bytes() {
+        let (sender, receiver) = RecordSender::new(Waker::detached());
+        let output = b"a.txt:1:caf\xe9 one\nb.txt:2:two\n";
+        parse_rg_output(&output[..], &ParseOptions::default(), &sender).unwrap();
+        drop(sender);
+        let texts: Vec<String> = receiver
+            .iter()
+            .map(|record| match_text(Some(record)))
+            .collect();
+        assert_eq!(texts, ["caf\u{fffd} one", "two"]);
+    }
 }
@@ -0,0 +1,114 @@
+use crate::rg_matches::RgMatch;
+use anyhow::{Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+// Number of remembered positions kept in the state file, oldest dropped first
+const MAX_POSITIONS: usize = 200;
+
+// Number of opened matches kept in the history file, oldest dropped first
+const MAX_HISTORY: usize = 100;
+
+// Function to get the location of a state file, `$XDG_CACHE_HOME/rgnav/<name>` falling back
+// to `~/.cache/rgnav/<name>`
+fn state_path(name: &str) -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("rgnav").join(name))
+}
+
+// Function to replace a state file, creating the cache directory if needed
+fn write_state(name: &str, contents: String) -> Result<()> {
+    let path = state_path(name).context("Cannot locate the cache directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Function to identify where the matches came from: the saved output file, or for stdin a
+// hash of the matches themselves
+pub fn position_key(input: Option<&str>, rg_matches: &[RgMatch]) -> String {
+    if let Some(path) = input {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        return format!("file:{}", path.display());
+    }
+
+    let mut hasher = DefaultHasher::new();
+    for data in rg_matches.iter().filter_map(|m| m.data.as_ref()) {
+        data.path.text.hash(&mut hasher);
+        data.line_number.hash(&mut hasher);
+    }
+    format!("stdin:{:016x}", hasher.finish())
+}
+
+// Function to read the saved `(key, match count, match index)` entries, oldest first
+fn read_positions() -> Vec<(String, usize, usize)> {
+    let Some(contents) = state_path("positions").and_then(|path| fs::read_to_string(path).ok())
+    else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let count = fields.next()?.parse().ok()?;
+            let idx = fields.next()?.parse().ok()?;
+            Some((fields.next()?.to_string(), count, idx))
+        })
+        .collect()
+}
+
+// Function to look up the index into the matches that was selected when `key` was last
+// browsed. A different number of matches means the position is stale.
+pub fn load_position(key: &str, match_count: usize) -> Option<usize> {
+    read_positions()
+        .into_iter()
+        .find(|(saved_key, count, idx)| {
+            saved_key == key && *count == match_count && *idx < match_count
+        })
+        .map(|(_, _, idx)| idx)
+}
+
+// Function to remember the selected match index for `key`
+pub fn save_position(key: &str, match_count: usize, idx: usize) -> Result<()> {
+    let mut positions = read_positions();
+    positions.retain(|(saved_key, _, _)| saved_key != key);
+    positions.push((key.to_string(), match_count, idx));
+    let skip = positions.len().saturating_sub(MAX_POSITIONS);
+
+    let contents: String = positions[skip..]
+        .iter()
+        .map(|(key, count, idx)| format!("{} {} {}\n", count, idx, key))
+        .collect();
+    write_state("positions", contents)
+}
+
+// Function to read the `(path, line)` pairs opened in earlier sessions, oldest first
+pub fn load_history() -> Vec<(String, usize)> {
+    let Some(contents) = state_path("history").and_then(|path| fs::read_to_string(path).ok())
+    else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (line_number, path) = line.split_once(' ')?;
+            Some((path.to_string(), line_number.parse().ok()?))
+        })
+        .collect()
+}
+
+// Function to save the opened `(path, line)` pairs for the next session, keeping the newest
+pub fn save_history(history: &[(String, usize)]) -> Result<()> {
+    let skip = history.len().saturating_sub(MAX_HISTORY);
+    let contents: String = history[skip..]
+        .iter()
+        .map(|(path, line)| format!("{} {}\n", line, path))
+        .collect();
+    write_state("history", contents)
+}
@@ -0,0 +1,105 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use ratatui::style::{Color, Modifier, Style};
+
+// Colors of the interface, from a built-in preset with the `[theme]` config section on top
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    // The selected row of the list
+    pub selected: Style,
+    // Borders of the list, preview and help panes
+    pub border: Style,
+    pub status_bar: Style,
+    // Background marking the matched line in the preview
+    pub match_line: Style,
+    // Lines ripgrep reported as context around a match, e.g. with `rg --json -C2`
+    pub context_line: Style,
+}
+
+impl Theme {
+    // Colors for dark terminal backgrounds, the default
+    const DARK: Theme = Theme {
+        selected: Style::new()
+            .bg(Color::Blue)
+            .fg(Color::White)
+            .add_modifier(Modifier::BOLD),
+        border: Style::new(),
+        status_bar: Style::new().bg(Color::DarkGray).fg(Color::White),
+        match_line: Style::new().bg(Color::Indexed(58)),
+        context_line: Style::new().bg(Color::Indexed(236)),
+    };
+
+    // Colors for light terminal backgrounds
+    const LIGHT: Theme = Theme {
+        selected: Style::new()
+            .bg(Color::LightBlue)
+            .fg(Color::Black)
+            .add_modifier(Modifier::BOLD),
+        border: Style::new().fg(Color::Gray),
+        status_bar: Style::new().bg(Color::Gray).fg(Color::Black),
+        match_line: Style::new().bg(Color::Indexed(230)),
+        context_line: Style::new().bg(Color::Indexed(255)),
+    };
+
+    // Styles without any colors, for `NO_COLOR` and `--no-color`
+    pub const MONOCHROME: Theme = Theme {
+        selected: Style::new().add_modifier(Modifier::REVERSED),
+        border: Style::new(),
+        status_bar: Style::new().add_modifier(Modifier::REVERSED),
+        match_line: Style::new().add_modifier(Modifier::BOLD),
+        context_line: Style::new(),
+    };
+
+    // Function to look up a built-in preset by name
+    fn preset(name: &str) -> Result<Self> {
+        match name {
+            "dark" => Ok(Self::DARK),
+            "light" => Ok(Self::LIGHT),
+            _ => Err(anyhow::anyhow!(
+                "Unknown theme `{}`, expected `dark` or `light`",
+                name
+            )),
+        }
+    }
+
+    // Function to build the theme from the `[theme]` config section. `preset` picks the
+    // starting colors; the other keys override single colors, given as names (`blue`,
+    // `lightred`), 256-color indices (`58`) or hex (`#e0e0e0`).
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut theme = Self::preset(config.get_str("theme", "preset")?.unwrap_or("dark"))?;
+        let color = |key: &str| -> Result<Option<Color>> {
+            config
+                .get_str("theme", key)?
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("Unknown color `{}`", value))
+                        .with_context(|| format!("Invalid value for `theme.{}`", key))
+                })
+                .transpose()
+        };
+
+        if let Some(fg) = color("selected_fg")? {
+            theme.selected = theme.selected.fg(fg);
+        }
+        if let Some(bg) = color("selected_bg")? {
+            theme.selected = theme.selected.bg(bg);
+        }
+        if let Some(fg) = color("border")? {
+            theme.border = theme.border.fg(fg);
+        }
+        if let Some(fg) = color("status_bar_fg")? {
+            theme.status_bar = theme.status_bar.fg(fg);
+        }
+        if let Some(bg) = color("status_bar_bg")? {
+            theme.status_bar = theme.status_bar.bg(bg);
+        }
+        if let Some(bg) = color("match_line_bg")? {
+            theme.match_line = theme.match_line.bg(bg);
+        }
+        if let Some(bg) = color("context_line_bg")? {
+            theme.context_line = theme.context_line.bg(bg);
+        }
+        Ok(theme)
+    }
+}
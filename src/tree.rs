@@ -0,0 +1,137 @@
+use crate::rg_matches::RgMatch;
+use std::collections::{HashMap, HashSet};
+
+// A row of the result list: a match, or in the tree view a directory heading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListRow {
+    Match {
+        // Index into `rg_matches`
+        idx: usize,
+        // Nesting level in the tree view, 0 in the flat list
+        depth: usize,
+    },
+    Dir {
+        // The directory as it appears in the matched paths, e.g. `src/ui`
+        path: String,
+        // What's shown for it under its parent, e.g. `ui`
+        name: String,
+        depth: usize,
+        // Matches anywhere below it
+        matches: usize,
+        collapsed: bool,
+    },
+}
+
+impl ListRow {
+    // Function to get the match a row stands for, if it isn't a directory
+    pub fn match_idx(&self) -> Option<usize> {
+        match self {
+            ListRow::Match { idx, .. } => Some(*idx),
+            ListRow::Dir { .. } => None,
+        }
+    }
+}
+
+// Function to list the given matches as they are, one row each
+pub fn flat_rows(indices: Vec<usize>) -> Vec<ListRow> {
+    indices
+        .into_iter()
+        .map(|idx| ListRow::Match { idx, depth: 0 })
+        .collect()
+}
+
+// One directory of the tree being built, with its entries in order of first appearance
+#[derive(Default)]
+struct Node {
+    path: String,
+    name: String,
+    entries: Vec<Entry>,
+    children: HashMap<String, usize>,
+    matches: usize,
+}
+
+enum Entry {
+    // Index into the node list
+    Dir(usize),
+    // Index into `rg_matches`
+    Match(usize),
+}
+
+// Function to nest the given matches under headings for each directory of their paths,
+// keeping them in the given order within a directory. Directories in `collapsed` get a
+// heading but none of their contents.
+pub fn tree_rows(
+    rg_matches: &[RgMatch],
+    indices: &[usize],
+    collapsed: &HashSet<String>,
+) -> Vec<ListRow> {
+    // The root is node 0; every other node is a directory prefix of some path
+    let mut nodes = vec![Node::default()];
+    for &idx in indices {
+        let Some(data) = rg_matches[idx].data.as_ref() else {
+            continue;
+        };
+        let path = &data.path.text;
+        let mut node = 0;
+        nodes[node].matches += 1;
+        let mut name_start = 0;
+        for (sep, _) in path.match_indices(['/', '\\']) {
+            // The leading `/` of an absolute path stays part of the first directory
+            if sep == 0 {
+                continue;
+            }
+            let prefix = &path[..sep];
+            let child = match nodes[node].children.get(prefix) {
+                Some(&child) => child,
+                None => {
+                    nodes.push(Node {
+                        path: prefix.to_string(),
+                        name: path[name_start..sep].to_string(),
+                        ..Node::default()
+                    });
+                    let child = nodes.len() - 1;
+                    nodes[node].children.insert(prefix.to_string(), child);
+                    nodes[node].entries.push(Entry::Dir(child));
+                    child
+                }
+            };
+            node = child;
+            nodes[node].matches += 1;
+            name_start = sep + 1;
+        }
+        nodes[node].entries.push(Entry::Match(idx));
+    }
+
+    let mut rows = Vec::new();
+    flatten(&nodes, 0, 0, collapsed, &mut rows);
+    rows
+}
+
+// Function to append the rows for a node's entries, depth-first
+fn flatten(
+    nodes: &[Node],
+    node: usize,
+    depth: usize,
+    collapsed: &HashSet<String>,
+    rows: &mut Vec<ListRow>,
+) {
+    for entry in &nodes[node].entries {
+        match *entry {
+            Entry::Match(idx) => rows.push(ListRow::Match { idx, depth }),
+            Entry::Dir(child) => {
+                let dir = &nodes[child];
+                let is_collapsed = collapsed.contains(&dir.path);
+                rows.push(ListRow::Dir {
+                    path: dir.path.clone(),
+                    name: dir.name.clone(),
+                    depth,
+                    matches: dir.matches,
+                    collapsed: is_collapsed,
+                });
+                if !is_collapsed {
+                    flatten(nodes, child, depth + 1, collapsed, rows);
+                }
+            }
+        }
+    }
+}